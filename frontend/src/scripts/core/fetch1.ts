@@ -2,26 +2,10 @@ import {Result} from "@/scripts/core/result";
 import {ApiError} from "@/scripts/core/error";
 
 export async function fetch1(input: RequestInfo | URL, init?: RequestInit): Promise<Result<Response, ApiError>> {
-  if (init) {
-    init.credentials = "include";
-    if (init.headers) {
-      init.headers = {
-        ...init.headers,
-        'Access-Control-Allow-Credentials': "true",
-      }
-    } else {
-      init.headers = {
-        'Access-Control-Allow-Credentials': "true",
-      };
-    }
-  } else {
-    init = {
-      credentials: "include",
-      headers: {
-        'Access-Control-Allow-Credentials': "true",
-      },
-    };
-  }
+  init = {
+    ...init,
+    credentials: "include",
+  };
 
   try {
     const r = await fetch(input, init);
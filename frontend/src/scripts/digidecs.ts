@@ -3,6 +3,9 @@ import {ApiError} from "@/scripts/core/error";
 import {fetch1} from "@/scripts/core/fetch1";
 import {server} from "@/main";
 
+const CHUNK_SIZE = 1024 * 1024;
+const MAX_UPLOAD_RETRIES = 5;
+
 export enum DigidecsLocale {
   NL,
   EN,
@@ -17,6 +20,83 @@ export namespace DigidecsLocale {
   }
 }
 
+export interface Challenge {
+  token: string;
+  difficulty: number;
+  min_fill_secs: number;
+}
+
+export interface ChallengeSolution {
+  token: string;
+  solution: string;
+}
+
+export namespace Challenge {
+  /**
+   * Request a challenge. Done when the form is loaded, as submitting sooner than `min_fill_secs` after is rejected.
+   */
+  export async function request(): Promise<Result<Challenge, ApiError>> {
+    const r = await fetch1(`${server}/api/digidecs/challenge`);
+    return r.map1(async (response) => <Challenge> await response.json());
+  }
+
+  /**
+   * Find a solution for which the SHA-256 of `<token>:<solution>` starts with `difficulty` zero bits.
+   */
+  export async function solve(challenge: Challenge): Promise<ChallengeSolution> {
+    const encoder = new TextEncoder();
+    for(let n = 0; challenge.difficulty > 0; n++) {
+      const digest = new Uint8Array(await crypto.subtle.digest('SHA-256', encoder.encode(`${challenge.token}:${n}`)));
+      if(leadingZeroBits(digest) >= challenge.difficulty) {
+        return {token: challenge.token, solution: n.toString()};
+      }
+    }
+
+    return {token: challenge.token, solution: ""};
+  }
+
+  function leadingZeroBits(bytes: Uint8Array): number {
+    let bits = 0;
+    for(const byte of bytes) {
+      if(byte == 0) {
+        bits += 8;
+        continue;
+      }
+
+      return bits + Math.clz32(byte) - 24;
+    }
+
+    return bits;
+  }
+}
+
+export interface User {
+  name: string;
+  email: string;
+  address: string | null;
+}
+
+export interface Session {
+  enabled: boolean;
+  required: boolean;
+  user?: User;
+}
+
+export namespace Session {
+  export async function get(): Promise<Result<Session, ApiError>> {
+    const r = await fetch1(`${server}/api/auth/session`);
+    return r.map1(async (response) => <Session> await response.json());
+  }
+
+  /**
+   * Send the browser to the login page of the issuer. It returns to the current page afterwards.
+   */
+  export function login() {
+    const returnTo = encodeURIComponent(window.location.pathname);
+    window.location.href = `${server}/api/auth/login?return_to=${returnTo}`;
+  }
+}
+
 export class Digidecs {
   trackingId: string;
   attachments: string[]
@@ -37,6 +117,8 @@ export class Digidecs {
     notes: string | null,
     attachments: File[],
     locale: DigidecsLocale,
+    website: string,
+    challenge: ChallengeSolution | null,
   ): Promise<Result<Digidecs, ApiError>> {
     const r = await fetch1(`${server}/api/digidecs/start`, {
       method: 'POST',
@@ -53,6 +135,8 @@ export class Digidecs {
         commission: commission,
         notes: notes,
         locale: DigidecsLocale.serverName(locale),
+        website: website,
+        challenge: challenge,
         attachments: attachments.map((att) => {
           return {
             name: att.name,
@@ -83,18 +167,59 @@ export class Digidecs {
   }
   
   async upload_attachment(file: File, index: number): Promise<Result<[], ApiError>> {
-    const r = await fetch1(`${server}/api/digidecs/attachment?tracking_id=${this.trackingId}&attachment_tracking_id=${this.attachments[index]}`, {
-      method: 'POST',
-      body: file,
-    });
-    
-    if(r.isOk()) {
-      return Result.ok([]);
-    } else {
-      return Result.err(r.unwrapErr());
+    const url = `${server}/api/digidecs/attachment?tracking_id=${this.trackingId}&attachment_tracking_id=${this.attachments[index]}`;
+    const sha256 = await sha256Hex(file);
+    const uploadUrl = sha256 ? `${url}&sha256=${sha256}` : url;
+
+    if(file.size <= CHUNK_SIZE) {
+      const r = await fetch1(uploadUrl, {
+        method: 'POST',
+        body: file,
+      });
+
+      return r.isOk() ? Result.ok([]) : Result.err(r.unwrapErr());
+    }
+
+    // Upload larger files in chunks, so an interrupted upload can continue where it left off
+    let offset = 0;
+    let failures = 0;
+    while(offset < file.size) {
+      const end = Math.min(offset + CHUNK_SIZE, file.size);
+      const r = await fetch1(uploadUrl, {
+        method: 'POST',
+        headers: {
+          'content-range': `bytes ${offset}-${end - 1}/${file.size}`,
+        },
+        body: file.slice(offset, end),
+      });
+
+      if(r.isOk()) {
+        offset = end;
+        failures = 0;
+        continue;
+      }
+
+      failures++;
+      if(failures > MAX_UPLOAD_RETRIES) {
+        return Result.err(r.unwrapErr());
+      }
+
+      await new Promise((resolve) => setTimeout(resolve, 1000 * failures));
+
+      interface UploadStatus {
+        received: number;
+      }
+
+      // Ask the server how much it received. If that fails too, retry the same chunk
+      const status = await fetch1(url);
+      if(status.isOk()) {
+        offset = (<UploadStatus> await status.unwrap().json()).received;
+      }
     }
+
+    return Result.ok([]);
   }
-  
+
   async complete(): Promise<Result<[], ApiError>> {
     const r = await fetch1(`${server}/api/digidecs/complete?tracking_id=${this.trackingId}`, {
       method: 'POST',
@@ -107,3 +232,15 @@ export class Digidecs {
     }
   }
 }
+
+async function sha256Hex(file: File): Promise<string | null> {
+  // Only available in secure contexts
+  if(!crypto.subtle) {
+    return null;
+  }
+
+  const digest = await crypto.subtle.digest('SHA-256', await file.arrayBuffer());
+  return Array.from(new Uint8Array(digest))
+    .map((b) => b.toString(16).padStart(2, '0'))
+    .join('');
+}
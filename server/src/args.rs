@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use std::path::PathBuf;
 
 #[derive(Debug, Clone, Parser)]
@@ -7,4 +7,54 @@ pub struct AppArgs {
     pub config: PathBuf,
     #[clap(long)]
     pub dry_run: bool,
+    /// Allow requests to the API from any origin, ignoring the configured CORS policy.
+    /// Only for development.
+    #[clap(long)]
+    pub permissive_cors: bool,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Render the email templates with sample data in every locale,
+    /// to preview changes to the templates without submitting a digidecs.
+    Render(RenderArgs),
+    /// Manage the API tokens giving access to the admin API
+    #[clap(subcommand)]
+    Token(TokenCommand),
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum TokenCommand {
+    /// Create a token, replacing any existing token with the same name.
+    /// The token is printed once and cannot be recovered afterwards.
+    Create {
+        /// Describes who or what uses the token, e.g. `bookkeeping`
+        name: String,
+    },
+    /// List the names of the tokens
+    List,
+    /// Revoke a token
+    Revoke { name: String },
+}
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct RenderArgs {
+    /// Only render this template. Renders all templates if omitted.
+    #[clap(long, short)]
+    pub template: Option<TemplateName>,
+    /// JSON file with the sample data, of the form `{"treasurer": {..}, "submitter": {..}}`.
+    /// Omitted fields are taken from the built-in sample data.
+    #[clap(long, short)]
+    pub sample: Option<PathBuf>,
+    /// Directory to write the rendered HTML and .eml files to
+    #[clap(long, short, default_value = "rendered")]
+    pub out: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TemplateName {
+    Treasurer,
+    Submitter,
 }
@@ -0,0 +1,327 @@
+use futures_util::future::join_all;
+use nix::errno::Errno;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tap::Tap;
+use thiserror::Error;
+use tracing::{info, trace, warn};
+
+use crate::file::SourceAddress;
+
+#[derive(Debug, Error)]
+pub enum AddressError {
+    #[error("Could not retrieve addresses ({errno}): {description}")]
+    GetAddresses {
+        errno: Errno,
+        description: &'static str,
+    },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("No local {0} address could be determined")]
+    Undeterminable(Family),
+    #[error("Could not resolve {0} address of {1}")]
+    NoRemote(Family, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    fn matches(&self, addr: &IpAddr) -> bool {
+        match self {
+            Self::V4 => addr.is_ipv4(),
+            Self::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+impl std::fmt::Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::V4 => f.write_str("IPv4"),
+            Self::V6 => f.write_str("IPv6"),
+        }
+    }
+}
+
+/// The local addresses SMTP connections are made from, per address family.
+/// If no address is set for a family, the operating system chooses.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SourceAddrs {
+    pub v4: Option<Ipv4Addr>,
+    pub v6: Option<Ipv6Addr>,
+}
+
+impl SourceAddrs {
+    /// The address to bind to when connecting to `remote`
+    pub fn bind_addr(&self, remote: &IpAddr) -> Option<IpAddr> {
+        match remote {
+            IpAddr::V4(_) => self.v4.map(IpAddr::V4),
+            IpAddr::V6(_) => self.v6.map(IpAddr::V6),
+        }
+    }
+}
+
+impl std::fmt::Display for SourceAddrs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show =
+            |addr: Option<IpAddr>| addr.map_or("chosen by OS".to_string(), |a| a.to_string());
+        write!(
+            f,
+            "IPv4 {}, IPv6 {}",
+            show(self.v4.map(IpAddr::V4)),
+            show(self.v6.map(IpAddr::V6))
+        )
+    }
+}
+
+/// The source addresses SMTP connections are made from.
+/// Detected addresses are checked periodically, and detected again once they no longer reach the probe target.
+pub struct SourceAddrMonitor {
+    config: SourceAddress,
+    probe: String,
+    current: RwLock<SourceAddrs>,
+}
+
+impl SourceAddrMonitor {
+    /// Determine the source addresses per the configured [SourceAddress].
+    /// `probe` is the `host:port` used to check if a local address can be used.
+    pub async fn new(config: SourceAddress, probe: String) -> Self {
+        let current = match config {
+            SourceAddress::Auto => detect(&probe).await,
+            SourceAddress::None => SourceAddrs::default(),
+            SourceAddress::Fixed(IpAddr::V4(addr)) => SourceAddrs {
+                v4: Some(addr),
+                v6: None,
+            },
+            SourceAddress::Fixed(IpAddr::V6(addr)) => SourceAddrs {
+                v4: None,
+                v6: Some(addr),
+            },
+        };
+
+        Self {
+            config,
+            probe,
+            current: RwLock::new(current),
+        }
+    }
+
+    pub fn current(&self) -> SourceAddrs {
+        *self.current.read().unwrap()
+    }
+
+    /// Periodically check if the detected addresses still reach the probe target, and detect them again if not.
+    /// Does nothing if the addresses are not detected automatically, or if `interval` is zero.
+    pub fn watch(self: Arc<Self>, interval: Duration) {
+        if self.config != SourceAddress::Auto || interval.is_zero() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(interval);
+            // The first tick completes immediately, the addresses were just detected
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+
+                // If nothing was detected the network may have come up since
+                let current = self.current();
+                let detected_any = current.v4.is_some() || current.v6.is_some();
+                if detected_any && self.reaches_probe(current).await {
+                    continue;
+                }
+
+                let detected = detect(&self.probe).await;
+                if detected != current {
+                    info!("Source addresses for SMTP connections changed to {detected}");
+                }
+                *self.current.write().unwrap() = detected;
+            }
+        });
+    }
+
+    async fn reaches_probe(&self, addrs: SourceAddrs) -> bool {
+        let Ok(remotes) = tokio::net::lookup_host(self.probe.as_str()).await else {
+            return false;
+        };
+        let remotes = remotes.collect::<Vec<_>>();
+
+        for local in [addrs.v4.map(IpAddr::V4), addrs.v6.map(IpAddr::V6)]
+            .into_iter()
+            .flatten()
+        {
+            let Some(remote) = remotes
+                .iter()
+                .find(|remote| remote.is_ipv4() == local.is_ipv4())
+            else {
+                return false;
+            };
+
+            if let Err(e) = connect(local, *remote).await {
+                warn!("Source address {local} can no longer reach {remote}: {e}");
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+async fn detect(probe: &str) -> SourceAddrs {
+    let (v4, v6) = tokio::join!(get_local_v4(probe), get_local_v6(probe));
+    if let (Err(e4), Err(e6)) = (&v4, &v6) {
+        warn!("Could not detect the local address for SMTP connections, letting the OS choose: {e4}; {e6}");
+    }
+
+    SourceAddrs {
+        v4: v4.ok(),
+        v6: v6.ok(),
+    }
+}
+
+/// Get the local IPv4 address of the machine which can reach `probe`
+///
+/// # Errors
+///
+/// If the operation fails
+pub async fn get_local_v4(probe: &str) -> Result<Ipv4Addr, AddressError> {
+    match get_local(Family::V4, probe).await? {
+        IpAddr::V4(addr) => Ok(addr),
+        IpAddr::V6(_) => Err(AddressError::Undeterminable(Family::V4)),
+    }
+}
+
+/// Get the local, globally routable, IPv6 address of the machine which can reach `probe`
+///
+/// # Errors
+///
+/// If the operation fails
+pub async fn get_local_v6(probe: &str) -> Result<Ipv6Addr, AddressError> {
+    match get_local(Family::V6, probe).await? {
+        IpAddr::V6(addr) => Ok(addr),
+        IpAddr::V4(_) => Err(AddressError::Undeterminable(Family::V6)),
+    }
+}
+
+async fn get_local(family: Family, probe: &str) -> Result<IpAddr, AddressError> {
+    let potential_addrs = nix::ifaddrs::getifaddrs()
+        .map_err(|e| AddressError::GetAddresses {
+            description: e.desc(),
+            errno: e,
+        })?
+        // Remove loopback
+        .filter_map(|iface| iface.address)
+        .collect::<Vec<_>>()
+        .tap(|addrs| trace!("Got {} addresses to try", addrs.len()))
+        .into_iter()
+        .filter_map(|addr| match family {
+            Family::V4 => addr.as_sockaddr_in().map(|addr4| IpAddr::V4(addr4.ip())),
+            Family::V6 => addr.as_sockaddr_in6().map(|addr6| IpAddr::V6(addr6.ip())),
+        })
+        .filter(|addr| {
+            let is_lo = addr.is_loopback();
+            let is_ll = match addr {
+                IpAddr::V4(addr) => addr.is_link_local(),
+                IpAddr::V6(addr) => addr.is_unicast_link_local(),
+            };
+
+            trace!("Address {addr:?} is loopback: {is_lo}; is link_local: {is_ll}");
+            !is_lo && !is_ll
+        })
+        .collect::<Vec<_>>();
+
+    trace!("Trying to determine {family} address of {probe}");
+    let remote_addr = tokio::net::lookup_host(probe)
+        .await?
+        .find(|addr| family.matches(&addr.ip()))
+        .ok_or_else(|| AddressError::NoRemote(family, probe.to_string()))?;
+    trace!("Determined address {remote_addr:?}");
+
+    // As we cannot determine if the address can reach the relay just by the address alone, try connecting over TCP
+    let connectable_addrs = join_all(potential_addrs.into_iter().map(|addr| async move {
+        connect(addr, remote_addr)
+            .await
+            .map(|_| addr)
+            .map_err(|e| (addr, e))
+    }))
+    .await
+    .into_iter()
+    .filter_map(|res| match res {
+        Ok(v) => Some(v),
+        Err((addr, e)) => {
+            trace!("Address {addr:?} could not reach {probe} due to {e}");
+            None
+        }
+    })
+    .collect::<Vec<_>>();
+
+    connectable_addrs
+        .first()
+        .copied()
+        .ok_or(AddressError::Undeterminable(family))
+}
+
+/// Open, and immediately close, a TCP connection from `local` to `remote`
+async fn connect(local: IpAddr, remote: SocketAddr) -> std::io::Result<()> {
+    let sock = match local {
+        IpAddr::V4(_) => tokio::net::TcpSocket::new_v4(),
+        IpAddr::V6(_) => tokio::net::TcpSocket::new_v6(),
+    }?;
+    sock.bind(SocketAddr::new(local, 0))?;
+
+    match tokio::time::timeout(Duration::from_secs(3), sock.connect(remote)).await {
+        Ok(stream) => stream.map(|_| ()),
+        Err(e) => Err(std::io::Error::new(std::io::ErrorKind::TimedOut, e)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // We assume there is always a non-loopback v4 address available!
+    // The probe listens on loopback, which accepts connections from any local address,
+    // so no network access is required.
+    #[tokio::test]
+    async fn get_local_ipv4() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let probe = listener.local_addr().unwrap().to_string();
+
+        let ip = super::get_local_v4(&probe).await;
+        assert!(ip.is_ok());
+        let ip = ip.unwrap();
+        assert!(!ip.is_loopback());
+    }
+
+    #[tokio::test]
+    async fn connect_to_probe() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote = listener.local_addr().unwrap();
+        let local = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+        assert!(connect(local, remote).await.is_ok());
+
+        drop(listener);
+        assert!(connect(local, remote).await.is_err());
+    }
+
+    #[test]
+    fn bind_addr_per_family() {
+        let addrs = SourceAddrs {
+            v4: Some(Ipv4Addr::new(192, 0, 2, 1)),
+            v6: None,
+        };
+
+        assert_eq!(
+            addrs.bind_addr(&IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1))),
+            Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)))
+        );
+        assert_eq!(addrs.bind_addr(&IpAddr::V6(Ipv6Addr::LOCALHOST)), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::file::{DkimAlgorithm, DkimConfig};
+use lettre::message::dkim::{
+    DkimCanonicalization, DkimCanonicalizationType, DkimSigningAlgorithm, DkimSigningKey,
+    DkimSigningKeyError,
+};
+use lettre::message::header::HeaderName;
+use thiserror::Error;
+use tracing::trace;
+
+pub use lettre::message::dkim::DkimConfig as DkimSigner;
+
+#[derive(Debug, Error)]
+pub enum DkimError {
+    #[error("Failed to read DKIM private key: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid DKIM private key: {0}")]
+    Key(#[from] DkimSigningKeyError),
+}
+
+/// The headers included in the signature.
+/// Reply-To is included as the treasurer email uses the address of the submitter there.
+const SIGNED_HEADERS: &[&str] = &["From", "To", "Subject", "Date", "Reply-To"];
+
+/// Load the private key from disk and create the signer used for all outgoing messages
+///
+/// # Errors
+///
+/// If the key could not be read or is not valid for the configured algorithm
+pub async fn load_signer(config: &DkimConfig) -> Result<DkimSigner, DkimError> {
+    trace!(
+        "Loading DKIM key from {}",
+        config.private_key_path.display()
+    );
+    let key = tokio::fs::read_to_string(&config.private_key_path).await?;
+    create_signer(config, key.trim())
+}
+
+fn create_signer(config: &DkimConfig, key: &str) -> Result<DkimSigner, DkimError> {
+    let algorithm = match config.algorithm {
+        DkimAlgorithm::Rsa => DkimSigningAlgorithm::Rsa,
+        DkimAlgorithm::Ed25519 => DkimSigningAlgorithm::Ed25519,
+    };
+
+    Ok(DkimSigner::new(
+        config.selector.clone(),
+        config.domain.clone(),
+        DkimSigningKey::new(key, algorithm)?,
+        SIGNED_HEADERS
+            .iter()
+            .map(|name| HeaderName::new_from_ascii_str(name))
+            .collect(),
+        DkimCanonicalization {
+            header: DkimCanonicalizationType::Relaxed,
+            body: DkimCanonicalizationType::Relaxed,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use lettre::message::SinglePart;
+    use lettre::Message;
+
+    fn config() -> DkimConfig {
+        DkimConfig {
+            selector: "digidecs".into(),
+            domain: "example.com".into(),
+            private_key_path: Default::default(),
+            algorithm: DkimAlgorithm::Ed25519,
+        }
+    }
+
+    #[test]
+    fn signs_message() {
+        let signer =
+            create_signer(&config(), "MDEyMzQ1Njc4OWFiY2RlZjAxMjM0NTY3ODlhYmNkZWY=").unwrap();
+
+        let mut msg = Message::builder()
+            .from("Digidecs <digidecs@example.com>".parse().unwrap())
+            .to("treasurer@example.com".parse().unwrap())
+            .subject("Test")
+            .singlepart(SinglePart::plain("Test".to_string()))
+            .unwrap();
+        msg.sign(&signer);
+
+        let formatted = String::from_utf8(msg.formatted()).unwrap();
+        assert!(formatted.contains("d=example.com; s=digidecs;"));
+    }
+
+    #[test]
+    fn invalid_key() {
+        assert!(create_signer(&config(), "not a key").is_err());
+    }
+}
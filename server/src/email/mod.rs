@@ -1,24 +1,31 @@
+use crate::email::address::SourceAddrs;
+use crate::email::dkim::DkimSigner;
+use crate::email::routing::Recipients;
 use crate::file::SmtpConfig;
+use crate::i18n::{Catalog, Locale};
+use crate::storage::Reference;
+use fluent_bundle::FluentArgs;
 use lettre::message::header::ContentType;
 use lettre::message::{Mailbox, MultiPart, SinglePart};
 use lettre::transport::smtp::client::{AsyncSmtpConnection, TlsParameters};
 use lettre::transport::smtp::extension::ClientId;
 use lettre::Address;
 use lettre::Message;
-use rand::Rng;
-use std::net::{IpAddr, Ipv4Addr};
 use std::str::FromStr;
 use std::time::Duration;
 use thiserror::Error;
 use tracing::{debug, error, trace};
 
-pub mod ipv4;
+pub mod address;
+pub mod dkim;
+pub mod routing;
 pub mod template;
+pub mod text;
 
-pub enum EmailLanguage {
-    Nl,
-    En,
-}
+/// The port on which the SMTP relay accepts submissions.
+/// We cannot do implicit TLS (which uses port 465, which is blocked by Hetzner), so use port 587.
+/// Port 587 starts out with regular SMTP commands, after the EHLO we upgrade to STARTTLS.
+pub const SUBMISSION_PORT: u16 = 587;
 
 #[derive(Debug, Error)]
 pub enum SendError {
@@ -32,10 +39,13 @@ pub enum SendError {
     Smtp(#[from] lettre::transport::smtp::Error),
     #[error("Could not connect to the server")]
     Connect,
+    #[error("Could not resolve the SMTP relay: {0}")]
+    Resolve(std::io::Error),
     #[error("Invalid MIME type: {0}")]
     ContentType(#[from] lettre::message::header::ContentTypeErr),
 }
 
+#[derive(Clone)]
 pub struct Attachment {
     pub content: Vec<u8>,
     pub name: String,
@@ -43,7 +53,10 @@ pub struct Attachment {
 }
 
 pub struct TreasurerEmailData<'a> {
-    pub to: &'a str,
+    pub catalog: &'a Catalog,
+    pub locale: &'a Locale,
+    pub reference: &'a Reference,
+    pub recipients: &'a Recipients,
     pub body: &'a str,
     pub reply_to_name: &'a str,
     pub reply_to_email: &'a str,
@@ -51,13 +64,11 @@ pub struct TreasurerEmailData<'a> {
     pub attachments: Vec<Attachment>,
 }
 
-pub async fn send_treasurer_email(
+/// Build the email to the treasurer. Send it with [send_email].
+pub fn treasurer_email(
     smtp_config: &SmtpConfig,
-    local_addr4: Ipv4Addr,
     data: TreasurerEmailData<'_>,
-) -> Result<(), SendError> {
-    let mb_to = Mailbox::from_str(data.to)?;
-
+) -> Result<Message, SendError> {
     let mb_from = Mailbox::new(
         Some(smtp_config.from_name.clone()),
         Address::from_str(&smtp_config.from_email)?,
@@ -68,93 +79,216 @@ pub async fn send_treasurer_email(
         Address::from_str(data.reply_to_email)?,
     );
 
-    let msg = Message::builder()
-        .reply_to(mb_reply_to)
-        .from(mb_from)
-        .to(mb_to)
-        .subject(format!(
-            "[DigiDecs] Nieuwe declaratie: {} ({})",
-            data.commission,
-            rand::thread_rng()
-                .sample_iter(rand::distributions::Alphanumeric)
-                .take(6)
-                .map(char::from)
-                .collect::<String>()
-        ));
+    let mut msg = Message::builder().reply_to(mb_reply_to).from(mb_from);
 
-    let mut mp = MultiPart::mixed().build();
-    mp = mp.singlepart(SinglePart::html(data.body.to_string()));
+    for to in &data.recipients.to {
+        msg = msg.to(Mailbox::from_str(to)?);
+    }
 
-    for att in data.attachments {
-        mp = mp.singlepart(
-            lettre::message::Attachment::new(att.name)
-                .body(att.content, ContentType::parse(&att.mime)?),
-        );
+    for cc in &data.recipients.cc {
+        msg = msg.cc(Mailbox::from_str(cc)?);
     }
 
-    let msg = msg.multipart(mp)?;
+    for bcc in &data.recipients.bcc {
+        msg = msg.bcc(Mailbox::from_str(bcc)?);
+    }
 
-    let mut conn = smtp_connect(smtp_config, local_addr4).await?;
+    let msg = msg
+        .message_id(Some(thread_message_id(smtp_config, data.reference)?))
+        .subject(subject(
+            data.catalog,
+            data.locale,
+            "treasurer-subject",
+            data.reference,
+            data.commission,
+        ));
 
-    trace!("Sending email");
-    conn.send(msg.envelope(), &msg.formatted()).await?;
+    Ok(msg.multipart(with_attachments(data.body.to_string(), data.attachments)?)?)
+}
 
-    Ok(())
+pub struct SubmitterEmailData<'a> {
+    pub catalog: &'a Catalog,
+    pub reference: &'a Reference,
+    pub to_email: &'a str,
+    pub name: &'a str,
+    pub body: String,
+    pub locale: &'a Locale,
+    pub commission: &'a str,
+    /// Copies of the attachments the submitter uploaded.
+    /// Empty if the attachments should not be sent back.
+    pub attachments: Vec<Attachment>,
 }
 
-pub async fn send_submitter_email(
+/// Build the confirmation email to the submitter. Send it with [send_email].
+pub fn submitter_email(
     smtp_config: &SmtpConfig,
-    local_addr4: Ipv4Addr,
-    to_email: &str,
-    body: String,
-    name: &str,
-    locale: &EmailLanguage,
-) -> Result<(), SendError> {
-    let mb_to = Mailbox::new(Some(name.to_string()), Address::from_str(to_email)?);
+    data: SubmitterEmailData<'_>,
+) -> Result<Message, SendError> {
+    let mb_to = Mailbox::new(
+        Some(data.name.to_string()),
+        Address::from_str(data.to_email)?,
+    );
 
     let mb_from = Mailbox::new(
         Some(smtp_config.from_name.clone()),
         Address::from_str(&smtp_config.from_email)?,
     );
 
+    let thread_id = thread_message_id(smtp_config, data.reference)?;
     let msg = Message::builder()
         .to(mb_to)
         .from(mb_from)
-        .subject(submitter_subject(locale))
-        .singlepart(SinglePart::html(body))?;
+        .message_id(Some(message_id(
+            smtp_config,
+            data.reference,
+            Some("confirmation"),
+        )?))
+        .in_reply_to(thread_id.clone())
+        .references(thread_id)
+        .subject(subject(
+            data.catalog,
+            data.locale,
+            "submitter-subject",
+            data.reference,
+            data.commission,
+        ));
+
+    Ok(if data.attachments.is_empty() {
+        msg.singlepart(SinglePart::html(data.body))?
+    } else {
+        msg.multipart(with_attachments(data.body, data.attachments)?)?
+    })
+}
+
+/// Whether the message is larger than the relay accepts.
+/// The DKIM signature is not yet included, that is small enough to not matter.
+pub fn exceeds_size_limit(smtp_config: &SmtpConfig, msg: &Message) -> bool {
+    msg.formatted().len() > smtp_config.max_message_size
+}
+
+/// Sign the message if DKIM is configured, and deliver it to the relay
+pub async fn send_email(
+    smtp_config: &SmtpConfig,
+    source_addrs: SourceAddrs,
+    dkim: Option<&DkimSigner>,
+    mut msg: Message,
+) -> Result<(), SendError> {
+    if let Some(dkim) = dkim {
+        trace!("Signing email with DKIM");
+        msg.sign(dkim);
+    }
 
-    let mut conn = smtp_connect(smtp_config, local_addr4).await?;
+    let mut conn = smtp_connect(smtp_config, source_addrs).await?;
     trace!("Sending email");
     conn.send(msg.envelope(), &msg.formatted()).await?;
 
     Ok(())
 }
 
-fn submitter_subject(locale: &EmailLanguage) -> String {
-    match locale {
-        EmailLanguage::Nl => "Je DigiDecs is ontvangen!".into(),
-        EmailLanguage::En => "Your DigiDecs has been received!".into(),
+/// Check that the relay accepts connections, by connecting and greeting it without sending anything
+pub async fn check_relay(
+    smtp_config: &SmtpConfig,
+    source_addrs: SourceAddrs,
+) -> Result<(), SendError> {
+    let mut conn = smtp_connect(smtp_config, source_addrs).await?;
+    conn.quit().await?;
+    Ok(())
+}
+
+fn with_attachments(body: String, attachments: Vec<Attachment>) -> Result<MultiPart, SendError> {
+    let mut mp = MultiPart::mixed().build();
+    mp = mp.singlepart(SinglePart::html(body));
+
+    for att in attachments {
+        mp = mp.singlepart(
+            lettre::message::Attachment::new(att.name)
+                .body(att.content, ContentType::parse(&att.mime)?),
+        );
     }
+
+    Ok(mp)
+}
+
+/// The Message-ID of the treasurer email, the first email sent about a declaration.
+/// Every other email about the declaration references it, so mail clients thread them together.
+fn thread_message_id(smtp_config: &SmtpConfig, reference: &Reference) -> Result<String, SendError> {
+    message_id(smtp_config, reference, None)
+}
+
+/// Deterministic Message-ID for an email about a declaration.
+/// `part` distinguishes between multiple emails about the same declaration.
+fn message_id(
+    smtp_config: &SmtpConfig,
+    reference: &Reference,
+    part: Option<&str>,
+) -> Result<String, SendError> {
+    let domain = get_ehlo_domain(&smtp_config.from_email).ok_or(SendError::EmailParse)?;
+    let local = reference.to_string().to_lowercase();
+
+    Ok(match part {
+        Some(part) => format!("<{local}.{part}@{domain}>"),
+        None => format!("<{local}@{domain}>"),
+    })
+}
+
+fn subject(
+    catalog: &Catalog,
+    locale: &Locale,
+    id: &str,
+    reference: &Reference,
+    commission: &str,
+) -> String {
+    let mut args = FluentArgs::new();
+    args.set("reference", reference.to_string());
+    args.set("commission", commission);
+
+    catalog
+        .format(locale, id, Some(&args))
+        .unwrap_or_else(|| format!("[DigiDecs] {reference}"))
 }
 
 async fn smtp_connect(
     config: &SmtpConfig,
-    bind_addr: Ipv4Addr,
+    source_addrs: SourceAddrs,
 ) -> Result<AsyncSmtpConnection, SendError> {
     let client_id =
         ClientId::Domain(get_ehlo_domain(&config.from_email).ok_or(SendError::EmailParse)?);
 
-    trace!("Opening SMTP connection");
-    let mut conn = AsyncSmtpConnection::connect_tokio1(
-        (config.smtp_relay.as_str(), 587),
-        Some(Duration::from_secs(3)),
-        &client_id,
-        // We cannot do STARTTLS (which uses port 465, which is blocked by Hetzner), so use port 587
-        // Port 587 starts out with regular SMTP commands, after the EHLO we upgrade to STARTTLS
-        None,
-        Some(IpAddr::V4(bind_addr)),
-    )
-    .await?;
+    // The relay may resolve to both A and AAAA records. Each family is connected to
+    // from the source address of that family, so try them in order until one connects.
+    let relay_addrs = tokio::net::lookup_host((config.smtp_relay.as_str(), SUBMISSION_PORT))
+        .await
+        .map_err(SendError::Resolve)?;
+
+    let mut last_err = None;
+    let mut connected = None;
+    for relay_addr in relay_addrs {
+        trace!("Opening SMTP connection to {relay_addr}");
+        match AsyncSmtpConnection::connect_tokio1(
+            relay_addr,
+            Some(Duration::from_secs(3)),
+            &client_id,
+            None,
+            source_addrs.bind_addr(&relay_addr.ip()),
+        )
+        .await
+        {
+            Ok(conn) => {
+                connected = Some(conn);
+                break;
+            }
+            Err(e) => {
+                debug!("Could not connect to {relay_addr}: {e}");
+                last_err = Some(e);
+            }
+        }
+    }
+
+    let mut conn = match (connected, last_err) {
+        (Some(conn), _) => conn,
+        (None, Some(e)) => return Err(e.into()),
+        (None, None) => return Err(SendError::Connect),
+    };
 
     if conn.can_starttls() {
         conn.starttls(
@@ -191,4 +325,25 @@ mod test {
         assert_eq!(get_ehlo_domain("example.org"), None);
         assert_eq!(get_ehlo_domain("example@"), Some(String::new()))
     }
+
+    #[test]
+    fn message_ids() {
+        let config = SmtpConfig {
+            from_email: "digidecs@example.com".into(),
+            ..Default::default()
+        };
+        let reference = Reference {
+            year: 2026,
+            number: 42,
+        };
+
+        assert_eq!(
+            thread_message_id(&config, &reference).unwrap(),
+            "<dd-2026-0042@example.com>"
+        );
+        assert_eq!(
+            message_id(&config, &reference, Some("confirmation")).unwrap(),
+            "<dd-2026-0042.confirmation@example.com>"
+        );
+    }
 }
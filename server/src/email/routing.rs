@@ -0,0 +1,154 @@
+use crate::file::{AppConfig, RoutingRule};
+use lettre::message::Mailbox;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The recipients of the treasurer email for a declaration
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipients {
+    /// The name of the routing rule that matched, `None` if the default was used
+    pub rule: Option<String>,
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+}
+
+/// Determine the recipients of the treasurer email.
+/// The first rule in the configuration that matches is used,
+/// if none match the email is sent to the configured treasurer.
+pub fn route(config: &AppConfig, commission: &str, value: f64) -> Recipients {
+    config
+        .routing
+        .iter()
+        .find(|rule| rule_matches(rule, commission, value))
+        .map(|rule| Recipients {
+            rule: Some(rule.name.clone()),
+            to: rule.to.clone(),
+            cc: rule.cc.clone(),
+            bcc: rule.bcc.clone(),
+        })
+        .unwrap_or_else(|| Recipients {
+            rule: None,
+            to: vec![config.treasurer_email.clone()],
+            cc: vec![],
+            bcc: vec![],
+        })
+}
+
+#[derive(Debug, Error)]
+pub enum RoutingError {
+    #[error("Routing rule '{0}' has no recipients in 'to'")]
+    NoRecipients(String),
+    #[error("Routing rule '{rule}' has an invalid email address '{address}'")]
+    InvalidAddress { rule: String, address: String },
+}
+
+/// Check that every rule can be used to send an email, so a mistake in the configuration
+/// is found at startup rather than when a declaration matches the rule
+///
+/// # Errors
+///
+/// If a rule has no `to` recipients, or an address that cannot be parsed
+pub fn validate(rules: &[RoutingRule]) -> Result<(), RoutingError> {
+    for rule in rules {
+        if rule.to.is_empty() {
+            return Err(RoutingError::NoRecipients(rule.name.clone()));
+        }
+
+        let invalid = rule
+            .to
+            .iter()
+            .chain(&rule.cc)
+            .chain(&rule.bcc)
+            .find(|address| Mailbox::from_str(address).is_err());
+        if let Some(address) = invalid {
+            return Err(RoutingError::InvalidAddress {
+                rule: rule.name.clone(),
+                address: address.clone(),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+fn rule_matches(rule: &RoutingRule, commission: &str, value: f64) -> bool {
+    let commission_matches = rule.commissions.is_empty()
+        || rule
+            .commissions
+            .iter()
+            .any(|c| c.trim().eq_ignore_ascii_case(commission.trim()));
+
+    let value_matches = rule.min_value.map(|min| value >= min).unwrap_or(true);
+
+    commission_matches && value_matches
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rule(name: &str, commissions: &[&str], min_value: Option<f64>) -> RoutingRule {
+        RoutingRule {
+            name: name.to_string(),
+            commissions: commissions.iter().map(|c| c.to_string()).collect(),
+            min_value,
+            to: vec![format!("{name}@example.com")],
+            cc: vec!["treasurer@example.com".to_string()],
+            bcc: vec![],
+        }
+    }
+
+    fn config() -> AppConfig {
+        AppConfig {
+            treasurer_email: "treasurer@example.com".to_string(),
+            routing: vec![
+                rule("large", &[], Some(1000.0)),
+                rule("lustrum", &["Lustrumcommissie"], None),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn default_recipient() {
+        let recipients = route(&config(), "Borrelcommissie", 10.0);
+        assert_eq!(recipients.rule, None);
+        assert_eq!(recipients.to, vec!["treasurer@example.com".to_string()]);
+        assert!(recipients.cc.is_empty());
+    }
+
+    #[test]
+    fn by_commission() {
+        let recipients = route(&config(), " lustrumcommissie", 10.0);
+        assert_eq!(recipients.rule.as_deref(), Some("lustrum"));
+        assert_eq!(recipients.to, vec!["lustrum@example.com".to_string()]);
+        assert_eq!(recipients.cc, vec!["treasurer@example.com".to_string()]);
+    }
+
+    #[test]
+    fn validate_rules() {
+        assert!(validate(&config().routing).is_ok());
+
+        let mut empty = rule("empty", &[], None);
+        empty.to.clear();
+        assert!(matches!(
+            validate(&[empty]),
+            Err(RoutingError::NoRecipients(rule)) if rule == "empty"
+        ));
+
+        let mut invalid = rule("invalid", &[], None);
+        invalid.bcc.push("not an address".to_string());
+        assert!(matches!(
+            validate(&[invalid]),
+            Err(RoutingError::InvalidAddress { address, .. }) if address == "not an address"
+        ));
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let recipients = route(&config(), "Lustrumcommissie", 1000.0);
+        assert_eq!(recipients.rule.as_deref(), Some("large"));
+    }
+}
@@ -1,14 +1,205 @@
-use crate::email::EmailLanguage;
-use handlebars::{Handlebars, RenderError};
-use serde::Serialize;
+use crate::email::text::UserTextHelper;
+use crate::i18n::{Catalog, Locale, TranslateHelper};
+use handlebars::{Handlebars, RenderError, TemplateError};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tracing::{debug, error, info};
 
 static TREASURER_TEMPLATE: &str = include_str!("templates/treasurer.hbs");
-static SUBMITTER_NL_TEMPLATE: &str = include_str!("templates/submitter_nl.hbs");
-static SUBMITTER_EN_TEMPLATE: &str = include_str!("templates/submitter_en.hbs");
+static SUBMITTER_TEMPLATE: &str = include_str!("templates/submitter.hbs");
 static HEADER_PARTIAL: &str = include_str!("templates/header.partial.hbs");
 
-#[derive(Debug, Serialize)]
+/// A built-in template or partial.
+/// A file with the same name in the template directory overrides the built-in one.
+struct Builtin {
+    /// The name under which the template is registered
+    name: &'static str,
+    file: &'static str,
+    source: &'static str,
+    partial: bool,
+}
+
+static BUILTIN_TEMPLATES: &[Builtin] = &[
+    Builtin {
+        name: "treasurer",
+        file: "treasurer.hbs",
+        source: TREASURER_TEMPLATE,
+        partial: false,
+    },
+    Builtin {
+        name: "submitter",
+        file: "submitter.hbs",
+        source: SUBMITTER_TEMPLATE,
+        partial: false,
+    },
+    Builtin {
+        name: "header",
+        file: "header.partial.hbs",
+        source: HEADER_PARTIAL,
+        partial: true,
+    },
+];
+
+/// How often the template directory is checked for changes
+const RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum TemplateLoadError {
+    #[error("Failed to read template {0}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid template {0}: {1}")]
+    Invalid(String, TemplateError),
+    #[error("Template {0} failed to render sample data: {1}")]
+    Render(String, RenderError),
+}
+
+/// The registry of all compiled email templates.
+/// Overrides from the template directory are reloaded when they change on disk.
+pub struct Templates {
+    dir: Option<PathBuf>,
+    catalog: Arc<Catalog>,
+    registry: RwLock<Handlebars<'static>>,
+}
+
+impl Templates {
+    /// Compile the templates, preferring files in `dir` over the built-in templates.
+    /// Every template is rendered with sample data in every locale, so errors surface now
+    /// instead of when the first digidecs is completed.
+    ///
+    /// # Errors
+    ///
+    /// If an override could not be read, is not a valid template or fails to render
+    pub fn load(dir: Option<&Path>, catalog: Arc<Catalog>) -> Result<Self, TemplateLoadError> {
+        Ok(Self {
+            dir: dir.map(Path::to_path_buf),
+            registry: RwLock::new(build_registry(dir, &catalog)?),
+            catalog,
+        })
+    }
+
+    fn render<T: Serialize>(&self, name: &str, data: &T) -> Result<String, RenderError> {
+        self.registry.read().unwrap().render(name, data)
+    }
+
+    /// Check that the templates in use render with sample data.
+    ///
+    /// # Errors
+    ///
+    /// If a template fails to render
+    pub fn verify(&self) -> Result<(), TemplateLoadError> {
+        render_samples(&self.registry.read().unwrap(), &self.catalog)
+    }
+
+    /// Periodically check the template directory for changes, and reload the templates if any.
+    /// If the changed templates are invalid the previous templates stay in use.
+    pub fn watch(self: Arc<Self>) {
+        let Some(dir) = self.dir.clone() else {
+            return;
+        };
+
+        tokio::spawn(async move {
+            let mut last_modified = modification_times(&dir);
+            let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+
+            loop {
+                interval.tick().await;
+
+                let modified = modification_times(&dir);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                match build_registry(Some(&dir), &self.catalog) {
+                    Ok(registry) => {
+                        info!("Templates in {} changed, reloaded", dir.display());
+                        *self.registry.write().unwrap() = registry;
+                    }
+                    Err(e) => error!("Templates changed, but could not be reloaded: {e}"),
+                }
+            }
+        });
+    }
+}
+
+fn build_registry(
+    dir: Option<&Path>,
+    catalog: &Arc<Catalog>,
+) -> Result<Handlebars<'static>, TemplateLoadError> {
+    let mut registry = Handlebars::new();
+    registry.set_strict_mode(true);
+    registry.register_helper(
+        "t",
+        Box::new(TranslateHelper {
+            catalog: catalog.clone(),
+        }),
+    );
+    registry.register_helper("text", Box::new(UserTextHelper { blocks: false }));
+    registry.register_helper("markdown", Box::new(UserTextHelper { blocks: true }));
+
+    for builtin in BUILTIN_TEMPLATES {
+        let source = match dir
+            .map(|dir| dir.join(builtin.file))
+            .filter(|path| path.exists())
+        {
+            Some(path) => {
+                debug!("Using template override {}", path.display());
+                std::fs::read_to_string(&path).map_err(|e| TemplateLoadError::Io(path, e))?
+            }
+            None => builtin.source.to_string(),
+        };
+
+        let registered = if builtin.partial {
+            registry.register_partial(builtin.name, source)
+        } else {
+            registry.register_template_string(builtin.name, source)
+        };
+        registered.map_err(|e| TemplateLoadError::Invalid(builtin.file.to_string(), e))?;
+    }
+
+    render_samples(&registry, catalog)?;
+    Ok(registry)
+}
+
+/// Render every template with sample data in every locale
+fn render_samples(registry: &Handlebars, catalog: &Catalog) -> Result<(), TemplateLoadError> {
+    let check = |name: &str, result: Result<String, RenderError>| {
+        result
+            .map(|_| ())
+            .map_err(|e| TemplateLoadError::Render(name.to_string(), e))
+    };
+    for locale in catalog.locales() {
+        let mut treasurer = TreasurerData::sample();
+        treasurer.locale = locale.clone();
+        check("treasurer", registry.render("treasurer", &treasurer))?;
+
+        let mut submitter = SubmitterData::sample();
+        submitter.locale = locale.clone();
+        check("submitter", registry.render("submitter", &submitter))?;
+    }
+
+    Ok(())
+}
+
+fn modification_times(dir: &Path) -> Vec<Option<SystemTime>> {
+    BUILTIN_TEMPLATES
+        .iter()
+        .map(|builtin| {
+            std::fs::metadata(dir.join(builtin.file))
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default = "TreasurerData::sample")]
 pub struct TreasurerData {
+    pub locale: Locale,
+    pub reference: String,
     pub name: String,
     pub iban: String,
     pub email: String,
@@ -17,36 +208,209 @@ pub struct TreasurerData {
     pub what: String,
     pub commission: String,
     pub notes: Option<String>,
+    /// Links to the attachments, if they were too large to be sent by email
+    pub download_links: Vec<DownloadLink>,
+    /// Date after which the download links no longer work
+    pub download_links_expire_at: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadLink {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default = "SubmitterData::sample")]
 pub struct SubmitterData {
+    pub locale: Locale,
     pub first_name: String,
+    pub reference: String,
+    pub value: String,
+    pub what: String,
+    pub commission: String,
+    /// IBAN with all but the country code and last four characters masked.
+    /// See [mask_iban].
+    pub iban: String,
+    pub attachments: Vec<String>,
+}
+
+impl TreasurerData {
+    /// Example data, used to verify templates
+    pub fn sample() -> Self {
+        Self {
+            locale: Locale::default(),
+            reference: "DD-2026-0042".to_string(),
+            name: "Jan Jansen".to_string(),
+            iban: "NL91ABNA0417164300".to_string(),
+            email: "jan@example.com".to_string(),
+            address: "Princetonplein 5, 3584 CC Utrecht".to_string(),
+            value: "42.50".to_string(),
+            what: "Boodschappen voor de borrel".to_string(),
+            commission: "Borrelcommissie".to_string(),
+            notes: Some(
+                "Betaald met eigen pas.\n\n- **Brood**\n- Kaas\n\nBon: https://example.com/bon"
+                    .to_string(),
+            ),
+            download_links: vec![DownloadLink {
+                name: "bon.pdf".to_string(),
+                url: "https://example.com/api/digidecs/download".to_string(),
+            }],
+            download_links_expire_at: Some("01-01-2026".to_string()),
+        }
+    }
 }
 
-pub fn render_treasurer(data: &TreasurerData) -> Result<String, RenderError> {
-    render_template(TREASURER_TEMPLATE, &data)
+impl SubmitterData {
+    /// Example data, used to verify templates
+    pub fn sample() -> Self {
+        Self {
+            locale: Locale::default(),
+            first_name: "Jan".to_string(),
+            reference: "DD-2026-0042".to_string(),
+            value: "42.50".to_string(),
+            what: "Boodschappen voor de borrel".to_string(),
+            commission: "Borrelcommissie".to_string(),
+            iban: mask_iban("NL91ABNA0417164300"),
+            attachments: vec!["bon.pdf".to_string()],
+        }
+    }
+}
+
+pub fn render_treasurer(
+    templates: &Templates,
+    data: &TreasurerData,
+) -> Result<String, RenderError> {
+    templates.render("treasurer", data)
 }
 
 pub fn render_submitter(
+    templates: &Templates,
     data: &SubmitterData,
-    locale: &EmailLanguage,
 ) -> Result<String, RenderError> {
-    let t = match locale {
-        EmailLanguage::Nl => SUBMITTER_NL_TEMPLATE,
-        EmailLanguage::En => SUBMITTER_EN_TEMPLATE,
-    };
+    templates.render("submitter", data)
+}
+
+/// Mask an IBAN so that it can be safely included in an email to the submitter.
+/// Only the country code and the last four characters remain visible,
+/// the result is grouped per four characters, like IBANs are usually printed.
+pub fn mask_iban(iban: &str) -> String {
+    let chars = iban
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<Vec<_>>();
+    let len = chars.len();
 
-    render_template(t, &data)
+    chars
+        .into_iter()
+        .enumerate()
+        .map(|(idx, c)| if idx < 2 || idx + 4 >= len { c } else { '*' })
+        .collect::<Vec<_>>()
+        .chunks(4)
+        .map(|chunk| chunk.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
-fn render_template<T: Serialize>(template: &str, data: &T) -> Result<String, RenderError> {
-    let mut engine = Handlebars::new();
-    engine.set_strict_mode(true);
-    engine.register_partial("header", HEADER_PARTIAL)?;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn iban_masked() {
+        assert_eq!(
+            mask_iban("NL91ABNA0417164300"),
+            "NL** **** **** **43 00".to_string()
+        );
+        assert_eq!(
+            mask_iban("NL91 ABNA 0417 1643 00"),
+            "NL** **** **** **43 00".to_string()
+        );
+        assert_eq!(mask_iban("NL91"), "NL91".to_string());
+    }
+
+    fn catalog() -> Arc<Catalog> {
+        Arc::new(Catalog::load(None, Locale::default()).unwrap())
+    }
+
+    #[test]
+    fn localized_templates() {
+        let templates = Templates::load(None, catalog()).unwrap();
+
+        let mut data = SubmitterData::sample();
+        data.locale = Locale::new("en");
+        let rendered = render_submitter(&templates, &data).unwrap();
+        assert!(rendered.contains(r#"<html lang="en">"#));
+        assert!(rendered.contains("Hi, Jan"));
+
+        data.first_name = "<b>Jan</b>".to_string();
+        let rendered = render_submitter(&templates, &data).unwrap();
+        assert!(rendered.contains("Hi, &lt;b&gt;Jan&lt;/b&gt;"));
+    }
+
+    #[test]
+    fn partial_sample_data() {
+        let data: TreasurerData =
+            serde_json::from_str(r#"{"name": "Piet", "notes": null}"#).unwrap();
+        assert_eq!(data.name, "Piet");
+        assert_eq!(data.notes, None);
+        assert_eq!(data.reference, TreasurerData::sample().reference);
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("digidecs-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn builtin_templates_render() {
+        let templates = Templates::load(None, catalog()).unwrap();
+        let rendered = render_treasurer(&templates, &TreasurerData::sample()).unwrap();
+        assert!(rendered.contains("DD-2026-0042"));
+        assert!(rendered.contains("<li><strong>Brood</strong></li>"));
+    }
+
+    #[test]
+    fn override_template() {
+        let dir = temp_dir("template-override");
+        std::fs::write(
+            dir.join("header.partial.hbs"),
+            "<head><title>Override</title></head>",
+        )
+        .unwrap();
+
+        let templates = Templates::load(Some(&dir), catalog()).unwrap();
+        let rendered = render_treasurer(&templates, &TreasurerData::sample()).unwrap();
+        assert!(rendered.contains("<title>Override</title>"));
+        assert!(rendered.contains("Nieuwe DigiDecs voor Borrelcommissie"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn override_fails_to_render() {
+        let dir = temp_dir("template-render");
+        std::fs::write(dir.join("submitter.hbs"), "{{ unknown_field }}").unwrap();
+
+        assert!(matches!(
+            Templates::load(Some(&dir), catalog()),
+            Err(TemplateLoadError::Render(_, _))
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_override() {
+        let dir = temp_dir("template-invalid");
+        std::fs::write(dir.join("treasurer.hbs"), "{{#if name}}").unwrap();
 
-    #[cfg(debug_assertions)]
-    engine.set_dev_mode(true);
+        assert!(matches!(
+            Templates::load(Some(&dir), catalog()),
+            Err(TemplateLoadError::Invalid(_, _))
+        ));
 
-    engine.render_template(template, data)
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
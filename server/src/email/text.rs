@@ -0,0 +1,200 @@
+use std::sync::OnceLock;
+
+use handlebars::{
+    html_escape, Context, Handlebars, Helper, HelperDef, HelperResult, JsonValue, Output,
+    RenderContext, RenderErrorReason,
+};
+use regex::Regex;
+
+/// Handlebars helpers rendering text entered by the submitter.
+///
+/// The text is HTML escaped, after which URLs are turned into links
+/// and `**bold**`, `*italic*` and `` `code` `` are formatted.
+/// - `{{text what}}` preserves line breaks.
+/// - `{{markdown notes}}` additionally turns blank lines into paragraphs
+///   and lines starting with `- ` or `* ` into lists.
+pub struct UserTextHelper {
+    pub blocks: bool,
+}
+
+impl UserTextHelper {
+    fn name(&self) -> &'static str {
+        if self.blocks {
+            "markdown"
+        } else {
+            "text"
+        }
+    }
+}
+
+impl HelperDef for UserTextHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        _: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let param = h
+            .param(0)
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex(self.name(), 0))?;
+
+        let text = match param.value() {
+            JsonValue::Null => return Ok(()),
+            JsonValue::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+
+        let rendered = if self.blocks {
+            render_blocks(&text)
+        } else {
+            render_lines(&text)
+        };
+
+        out.write(&rendered)?;
+        Ok(())
+    }
+}
+
+/// Render text with its line breaks preserved
+pub fn render_lines(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(render_inline)
+        .collect::<Vec<_>>()
+        .join("<br/>\n")
+}
+
+/// Render text as paragraphs and lists, separated by blank lines
+pub fn render_blocks(text: &str) -> String {
+    let mut blocks = vec![];
+    let mut current = vec![];
+
+    for line in text.lines().map(str::trim_end) {
+        if line.trim().is_empty() {
+            if !current.is_empty() {
+                blocks.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        blocks.push(current);
+    }
+
+    blocks
+        .into_iter()
+        .map(|lines| {
+            let items = lines
+                .iter()
+                .map(|line| list_item(line))
+                .collect::<Option<Vec<_>>>();
+
+            match items {
+                Some(items) => format!(
+                    "<ul>\n{}\n</ul>",
+                    items
+                        .into_iter()
+                        .map(|item| format!("<li>{}</li>", render_inline(item)))
+                        .collect::<Vec<_>>()
+                        .join("\n")
+                ),
+                None => format!("<p>{}</p>", render_lines(&lines.join("\n"))),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn list_item(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    line.strip_prefix("- ").or_else(|| line.strip_prefix("* "))
+}
+
+static INLINE_REGEX: OnceLock<Regex> = OnceLock::new();
+
+/// Escape a single line, and format the links and inline Markdown in it.
+/// Everything is escaped before it ends up in the output, so the text cannot inject HTML.
+fn render_inline(line: &str) -> String {
+    let regex = INLINE_REGEX.get_or_init(|| {
+        Regex::new(
+            r#"(?P<url>https?://[^\s<>"]+)|\*\*(?P<bold>[^*]+)\*\*|\*(?P<italic>[^*\s][^*]*)\*|`(?P<code>[^`]+)`"#,
+        )
+        .unwrap()
+    });
+
+    let mut out = String::new();
+    let mut last = 0;
+
+    for captures in regex.captures_iter(line) {
+        let matched = captures.get(0).unwrap();
+        out.push_str(&html_escape(&line[last..matched.start()]));
+        last = matched.end();
+
+        if let Some(url) = captures.name("url") {
+            // Punctuation directly after a URL most likely ends the sentence
+            let url = url
+                .as_str()
+                .trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+            last = matched.start() + url.len();
+
+            let url = html_escape(url);
+            out.push_str(&format!(r#"<a href="{url}">{url}</a>"#));
+        } else if let Some(bold) = captures.name("bold") {
+            out.push_str(&format!("<strong>{}</strong>", html_escape(bold.as_str())));
+        } else if let Some(italic) = captures.name("italic") {
+            out.push_str(&format!("<em>{}</em>", html_escape(italic.as_str())));
+        } else if let Some(code) = captures.name("code") {
+            out.push_str(&format!("<code>{}</code>", html_escape(code.as_str())));
+        }
+    }
+
+    out.push_str(&html_escape(&line[last..]));
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(render_lines("a\nb\r\nc"), "a<br/>\nb<br/>\nc");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(
+            render_lines("<script>alert(1)</script>"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+        assert_eq!(render_inline("**<b>**"), "<strong>&lt;b&gt;</strong>");
+        assert!(
+            !render_inline(r#"https://example.com/"onmouseover="alert(1)"#)
+                .contains(r#""onmouseover"#)
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            render_inline("See https://example.com/bon.pdf."),
+            r#"See <a href="https://example.com/bon.pdf">https://example.com/bon.pdf</a>."#
+        );
+        assert_eq!(render_inline("javascript:alert(1)"), "javascript:alert(1)");
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            render_inline("**bold**, *italic* and `code`, 2 * 3"),
+            "<strong>bold</strong>, <em>italic</em> and <code>code</code>, 2 * 3"
+        );
+        assert_eq!(
+            render_blocks("Bought:\n\n- bread\n* cheese\n\n\nThanks\nJan"),
+            "<p>Bought:</p>\n<ul>\n<li>bread</li>\n<li>cheese</li>\n</ul>\n<p>Thanks<br/>\nJan</p>"
+        );
+    }
+}
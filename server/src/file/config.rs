@@ -1,38 +1,535 @@
 use crate::file::DataFile;
+use crate::i18n::Locale;
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::PathBuf;
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct AppConfig {
     pub server: ServerConfig,
     pub smtp: SmtpConfig,
     pub treasurer_email: String,
+    /// Whether the confirmation email to the submitter should
+    /// include a copy of the uploaded attachments.
+    #[serde(default)]
+    pub submitter_attachments: bool,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub uploads: UploadConfig,
+    /// Rules determining who receives the treasurer email.
+    /// The first matching rule is used, if no rule matches the email is sent to `treasurer_email`.
+    #[serde(default)]
+    pub routing: Vec<RoutingRule>,
+    /// Key used to sign values handed out to clients, such as download links.
+    /// If not set, a random key is generated at startup and such values become invalid after a restart.
+    #[serde(default)]
+    pub signing_key: Option<String>,
+    /// Directory with email templates overriding the built-in ones, by file name.
+    /// Changes are picked up while running.
+    #[serde(default)]
+    pub templates_dir: Option<PathBuf>,
+    /// Directory with additional message catalogs, named `<language tag>.ftl`.
+    /// A catalog with the same language tag as a built-in one overrides it.
+    #[serde(default)]
+    pub locales_dir: Option<PathBuf>,
+    /// Locale used when no catalog exists for the requested locale,
+    /// and for the email to the treasurer
+    #[serde(default)]
+    pub default_locale: Locale,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub bot_protection: BotProtectionConfig,
+    /// Login of members with OpenID Connect. If not set, anyone can submit a declaration.
+    #[serde(default)]
+    pub oidc: Option<OidcConfig>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServerConfig {
     #[serde(default = "default_port")]
     pub port: u16,
+    /// The addresses to listen on. IPv6 addresses only accept IPv6 connections,
+    /// so listen on both `0.0.0.0` and `::` for dual-stack.
+    #[serde(default = "default_listen")]
+    pub listen: Vec<IpAddr>,
     pub domain: String,
+    /// The URL under which the server is reachable for users, used in links in emails.
+    /// Defaults to `https://<domain>`.
+    #[serde(default)]
+    pub public_url: Option<String>,
+    /// How long download links for attachments that were too large to email remain valid
+    #[serde(default = "default_download_link_validity_days")]
+    pub download_link_validity_days: i64,
+    #[serde(default)]
+    pub cors: CorsConfig,
 }
 
-#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+/// Which other origins may use the API from a browser
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CorsConfig {
+    /// Origins, e.g. `https://example.com`, allowed to make requests.
+    /// Defaults to the origin of the public URL.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_cors_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers allowed in addition to the CORS-safelisted ones
+    #[serde(default = "default_cors_headers")]
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the result of a preflight request
+    #[serde(default = "default_cors_max_age_secs")]
+    pub max_age_secs: usize,
+}
+
+impl ServerConfig {
+    pub fn public_url(&self) -> String {
+        self.public_url
+            .clone()
+            .unwrap_or_else(|| format!("https://{}", self.domain))
+            .trim_end_matches('/')
+            .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct SmtpConfig {
     pub from_email: String,
     pub from_name: String,
     pub smtp_relay: String,
+    /// If set, every outgoing message is DKIM signed
+    #[serde(default)]
+    pub dkim: Option<DkimConfig>,
+    /// The largest message in bytes, after encoding, the relay accepts.
+    /// Attachments of larger messages are replaced by download links.
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+    /// The local address SMTP connections are made from
+    #[serde(default)]
+    pub source_address: SourceAddress,
+    /// The `host:port` connected to, to check if a local address can be used for SMTP connections.
+    /// Defaults to the SMTP relay.
+    #[serde(default)]
+    pub probe: Option<String>,
+    /// How often detected source addresses are checked to still reach the probe target.
+    /// `0` disables the checks, the addresses are then only detected at startup.
+    #[serde(default = "default_probe_interval_secs")]
+    pub probe_interval_secs: u64,
+}
+
+impl SmtpConfig {
+    pub fn probe(&self) -> String {
+        self.probe
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", self.smtp_relay, crate::email::SUBMISSION_PORT))
+    }
+}
+
+/// The local address SMTP connections are made from.
+/// Configured as `"auto"`, `"none"` or an IP address.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SourceAddress {
+    /// Detect the addresses of interfaces that can reach the internet at startup, for both IPv4 and IPv6.
+    /// If no such address is found for a family, the operating system chooses.
+    #[default]
+    Auto,
+    /// Let the operating system choose
+    None,
+    /// Use this address for connections of its family, the operating system chooses for the other family
+    Fixed(IpAddr),
+}
+
+impl TryFrom<String> for SourceAddress {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "auto" => Ok(Self::Auto),
+            "none" => Ok(Self::None),
+            addr => addr
+                .parse()
+                .map(Self::Fixed)
+                .map_err(|_| format!("Expected 'auto', 'none' or an IP address, got '{addr}'")),
+        }
+    }
+}
+
+impl From<SourceAddress> for String {
+    fn from(value: SourceAddress) -> Self {
+        value.to_string()
+    }
+}
+
+impl Display for SourceAddress {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Auto => f.write_str("auto"),
+            Self::None => f.write_str("none"),
+            Self::Fixed(addr) => addr.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DkimConfig {
+    /// The selector under which the public key is published in DNS,
+    /// i.e. `<selector>._domainkey.<domain>`
+    pub selector: String,
+    /// The signing domain
+    pub domain: String,
+    /// Path to the private key.
+    /// For RSA this is a PKCS#1 PEM file, for Ed25519 the base64 encoded raw key.
+    pub private_key_path: PathBuf,
+    #[serde(default)]
+    pub algorithm: DkimAlgorithm,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DkimAlgorithm {
+    #[default]
+    Rsa,
+    Ed25519,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoutingRule {
+    /// Name of the rule, used for logging and recorded with the declaration
+    pub name: String,
+    /// The committees this rule applies to, compared case-insensitively.
+    /// If empty, the rule applies to every committee.
+    #[serde(default)]
+    pub commissions: Vec<String>,
+    /// The rule only applies to declarations of at least this amount
+    #[serde(default)]
+    pub min_value: Option<f64>,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+    #[serde(default)]
+    pub bcc: Vec<String>,
+}
+
+/// Limits on submissions, to protect against clients starting declarations in a loop.
+/// Exceeding a limit is answered with `429 Too Many Requests`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    /// Submissions per client IP address. IPv6 addresses are limited per /64.
+    /// `null` disables the limit.
+    #[serde(default = "default_per_ip")]
+    pub per_ip: Option<TokenBucketConfig>,
+    /// Submissions per submitter email address. `null` disables the limit.
+    #[serde(default = "default_per_email")]
+    pub per_email: Option<TokenBucketConfig>,
+    /// The most declarations that may be started, but not yet completed, at the same time
+    #[serde(default = "default_max_pending")]
+    pub max_pending: usize,
+    /// Header in which a reverse proxy passes the client address, e.g. `X-Forwarded-For`.
+    /// Only honoured for connections from `trusted_proxies`.
+    #[serde(default)]
+    pub client_ip_header: Option<String>,
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+}
+
+/// A token bucket: up to `burst` requests at once, after which `per_hour` requests per hour are allowed
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct TokenBucketConfig {
+    pub burst: u32,
+    pub per_hour: u32,
+}
+
+/// Checks rejecting submissions made by bots.
+/// The minimum fill time and proof of work require the form to request a challenge first.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BotProtectionConfig {
+    /// Reject submissions in which the hidden `website` field is filled in
+    #[serde(default = "default_honeypot")]
+    pub honeypot: bool,
+    /// Reject submissions made sooner than this after the form requested its challenge
+    #[serde(default)]
+    pub min_fill_secs: Option<u64>,
+    /// Require a proof of work with this many leading zero bits.
+    /// Every bit doubles the expected work, 16 takes a browser about a second.
+    #[serde(default)]
+    pub proof_of_work_bits: Option<u8>,
+    /// How long a challenge can be used
+    #[serde(default = "default_challenge_validity_secs")]
+    pub challenge_validity_secs: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OidcConfig {
+    /// The issuer URL, e.g. `https://login.example.com/realms/members`.
+    /// Its endpoints are discovered from `<issuer>/.well-known/openid-configuration`.
+    pub issuer: String,
+    pub client_id: String,
+    /// Not needed if the client is registered as a public client
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    #[serde(default = "default_oidc_scopes")]
+    pub scopes: Vec<String>,
+    /// Whether declarations can only be submitted by members that logged in
+    #[serde(default = "default_oidc_required")]
+    pub required: bool,
+    /// How long a login lasts
+    #[serde(default = "default_session_hours")]
+    pub session_hours: i64,
+    /// Members with this role can use the admin API
+    #[serde(default)]
+    pub treasurer_role: Option<String>,
+    /// The ID token claim holding the roles of the member.
+    /// Nested claims are separated by dots, e.g. `realm_access.roles`.
+    #[serde(default = "default_roles_claim")]
+    pub roles_claim: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StorageConfig {
+    /// Directory in which completed declarations and the reference counter are stored
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+}
+
+/// Limits on files uploaded with a submission.
+/// The attachments of a declaration are held in memory while it is sent,
+/// so `max_total_size` bounds the memory used per submission.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UploadConfig {
+    /// The largest accepted file, in bytes
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// The largest accepted total size of the files of a submission, in bytes
+    #[serde(default = "default_max_total_size")]
+    pub max_total_size: u64,
+    /// The most files accepted with a submission
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
 }
 
 fn default_port() -> u16 {
     8080
 }
 
+fn default_listen() -> Vec<IpAddr> {
+    vec![IpAddr::V4(Ipv4Addr::UNSPECIFIED)]
+}
+
+fn default_download_link_validity_days() -> i64 {
+    30
+}
+
+fn default_cors_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+fn default_cors_headers() -> Vec<String> {
+    vec![
+        "Content-Type".to_string(),
+        "Content-Range".to_string(),
+        "Accept-Language".to_string(),
+    ]
+}
+
+fn default_cors_max_age_secs() -> usize {
+    3600
+}
+
+fn default_max_file_size() -> u64 {
+    15_000_000
+}
+
+fn default_max_total_size() -> u64 {
+    25_000_000
+}
+
+fn default_max_files() -> usize {
+    20
+}
+
+fn default_max_message_size() -> usize {
+    // Google's SMTP relay accepts messages up to 25 MB
+    25_000_000
+}
+
+fn default_probe_interval_secs() -> u64 {
+    300
+}
+
+fn default_per_ip() -> Option<TokenBucketConfig> {
+    Some(TokenBucketConfig {
+        burst: 20,
+        per_hour: 60,
+    })
+}
+
+fn default_per_email() -> Option<TokenBucketConfig> {
+    Some(TokenBucketConfig {
+        burst: 10,
+        per_hour: 30,
+    })
+}
+
+fn default_max_pending() -> usize {
+    1000
+}
+
+fn default_honeypot() -> bool {
+    true
+}
+
+fn default_challenge_validity_secs() -> u64 {
+    // Filling in the form, and finding the receipts, can take a while
+    24 * 3600
+}
+
+fn default_oidc_scopes() -> Vec<String> {
+    vec![
+        "openid".to_string(),
+        "profile".to_string(),
+        "email".to_string(),
+    ]
+}
+
+fn default_oidc_required() -> bool {
+    true
+}
+
+fn default_session_hours() -> i64 {
+    12
+}
+
+fn default_roles_claim() -> String {
+    "roles".to_string()
+}
+
+fn default_data_dir() -> PathBuf {
+    PathBuf::from("data")
+}
+
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
             port: default_port(),
+            listen: default_listen(),
             domain: String::new(),
+            public_url: None,
+            download_link_validity_days: default_download_link_validity_days(),
+            cors: CorsConfig::default(),
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: default_cors_methods(),
+            allowed_headers: default_cors_headers(),
+            max_age_secs: default_cors_max_age_secs(),
+        }
+    }
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            from_email: String::new(),
+            from_name: String::new(),
+            smtp_relay: String::new(),
+            dkim: None,
+            max_message_size: default_max_message_size(),
+            source_address: SourceAddress::default(),
+            probe: None,
+            probe_interval_secs: default_probe_interval_secs(),
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            per_ip: default_per_ip(),
+            per_email: default_per_email(),
+            max_pending: default_max_pending(),
+            client_ip_header: None,
+            trusted_proxies: vec![],
+        }
+    }
+}
+
+impl Default for BotProtectionConfig {
+    fn default() -> Self {
+        Self {
+            honeypot: default_honeypot(),
+            min_fill_secs: None,
+            proof_of_work_bits: None,
+            challenge_validity_secs: default_challenge_validity_secs(),
+        }
+    }
+}
+
+impl Default for OidcConfig {
+    fn default() -> Self {
+        Self {
+            issuer: String::new(),
+            client_id: String::new(),
+            client_secret: None,
+            scopes: default_oidc_scopes(),
+            required: default_oidc_required(),
+            session_hours: default_session_hours(),
+            treasurer_role: None,
+            roles_claim: default_roles_claim(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: default_data_dir(),
+        }
+    }
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: default_max_file_size(),
+            max_total_size: default_max_total_size(),
+            max_files: default_max_files(),
         }
     }
 }
 
 impl DataFile for AppConfig {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn source_address() {
+        let parse = |value: &str| serde_json::from_str::<SourceAddress>(value);
+
+        assert_eq!(parse(r#""auto""#).unwrap(), SourceAddress::Auto);
+        assert_eq!(parse(r#""none""#).unwrap(), SourceAddress::None);
+        assert_eq!(
+            parse(r#""192.0.2.1""#).unwrap(),
+            SourceAddress::Fixed(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)))
+        );
+        assert_eq!(
+            parse(r#""2001:db8::1""#).unwrap(),
+            SourceAddress::Fixed("2001:db8::1".parse().unwrap())
+        );
+        assert!(parse(r#""localhost""#).is_err());
+        assert_eq!(
+            serde_json::to_string(&SourceAddress::Auto).unwrap(),
+            r#""auto""#
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use std::sync::Arc;
+
+use fluent_bundle::FluentArgs;
+use handlebars::{
+    html_escape, Context, Handlebars, Helper, HelperDef, HelperResult, JsonValue, Output,
+    RenderContext, RenderErrorReason,
+};
+
+use crate::i18n::{Catalog, Locale};
+
+/// Handlebars helper translating a message from the [Catalog].
+///
+/// `{{t "submitter-greeting" name=first_name}}` formats the message `submitter-greeting`,
+/// with the argument `name`, in the locale given by the `locale` field of the template data.
+/// Arguments are HTML escaped, the message itself is not, so messages may contain markup.
+pub struct TranslateHelper {
+    pub catalog: Arc<Catalog>,
+}
+
+impl HelperDef for TranslateHelper {
+    fn call<'reg: 'rc, 'rc>(
+        &self,
+        h: &Helper<'rc>,
+        _: &'reg Handlebars<'reg>,
+        ctx: &'rc Context,
+        _: &mut RenderContext<'reg, 'rc>,
+        out: &mut dyn Output,
+    ) -> HelperResult {
+        let id = h
+            .param(0)
+            .and_then(|param| param.value().as_str())
+            .ok_or(RenderErrorReason::ParamNotFoundForIndex("t", 0))?;
+
+        let locale = ctx
+            .data()
+            .get("locale")
+            .and_then(JsonValue::as_str)
+            .map(Locale::new)
+            .unwrap_or_else(|| self.catalog.default_locale().clone());
+
+        let mut args = FluentArgs::new();
+        for (name, value) in h.hash() {
+            match value.value() {
+                JsonValue::Number(n) => args.set(*name, n.as_f64().unwrap_or_default()),
+                JsonValue::String(s) => args.set(*name, html_escape(s)),
+                other => args.set(*name, html_escape(&other.to_string())),
+            }
+        }
+
+        let message = self
+            .catalog
+            .format(&locale, id, Some(&args))
+            .ok_or_else(|| RenderErrorReason::Other(format!("Unknown message {id}")))?;
+
+        out.write(&message)?;
+        Ok(())
+    }
+}
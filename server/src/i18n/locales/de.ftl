@@ -0,0 +1,66 @@
+## Email subjects
+
+treasurer-subject = [DigiDecs] Neue Erstattung { $reference }: { $commission }
+submitter-subject = Deine DigiDecs ist eingegangen! ({ $reference })
+
+## Labels shared by the emails
+
+label-reference = Referenz
+label-name = Name
+label-email = E-Mail
+label-address = Adresse
+label-value = Gesamtbetrag
+label-what = Was
+label-commission = Wofür
+label-iban = Kontonummer
+label-notes = Anmerkungen
+label-attachments = Anhänge
+
+## Email to the treasurer
+
+treasurer-banner = Neue DigiDecs für { $commission }
+treasurer-download-links = Die Anhänge waren zu groß für eine E-Mail. Sie können bis { $expires } heruntergeladen werden:
+
+## Confirmation email to the submitter
+
+submitter-banner = DigiDecs eingegangen!
+submitter-greeting = Hallo, { $name }
+submitter-received = Wir haben deine DigiDecs erhalten. Falls du dein Geld nach 7 Werktagen noch nicht erhalten hast, wende dich bitte an den <a href="mailto:penningmeester@svsticky.nl">Kassenwart</a>.
+submitter-summary = Das haben wir erhalten. Falls etwas nicht stimmt, sag dem Kassenwart bitte so schnell wie möglich Bescheid.
+submitter-closing = Mit freundlichen Grüßen,<br/>Der Vorstand
+
+## API errors
+
+error-internal = Bei uns ist etwas schiefgelaufen. Bitte versuche es später erneut.
+error-invalid-iban = Ungültige IBAN
+error-invalid-email = Ungültige E-Mail-Adresse
+error-invalid-address = Ungültige Adresse
+error-missing-attachment = Anhänge fehlen
+error-value-not-positive = Der Betrag muss größer als null sein
+error-invalid-attachment = Der Anhang konnte nicht gelesen werden
+error-unknown-tracking-id = Diese DigiDecs existiert nicht. Fang bitte neu an
+error-unknown-attachment-tracking-id = Dieser Anhang existiert nicht. Fang bitte neu an
+error-digidecs-expired = Diese DigiDecs ist abgelaufen. Fang bitte neu an
+error-invalid-signature = Dieser Link ist ungültig
+error-link-expired = Dieser Link ist abgelaufen
+error-unknown-attachment = Dieser Anhang existiert nicht
+error-body-too-large = Die Datei ist zu groß
+error-validation = Nicht alle Felder sind korrekt ausgefüllt
+error-invalid-field = Dieses Feld fehlt oder ist ungültig
+error-invalid-form = Das Formular konnte nicht gelesen werden
+error-invalid-content-range = Der Upload konnte nicht fortgesetzt werden
+error-upload-offset-mismatch = Der Upload wurde unterbrochen. Versuche es erneut
+error-checksum-mismatch = Der Anhang wurde beim Hochladen beschädigt. Versuche es erneut
+error-rate-limited = Zu viele Anfragen. Versuche es später erneut
+error-bot-detected = Deine Einsendung wurde abgelehnt
+error-invalid-challenge = Das Formular ist abgelaufen. Lade die Seite neu und versuche es erneut
+error-submitted-too-fast = Das Formular wurde zu schnell abgeschickt. Warte einen Moment und versuche es erneut
+error-unauthenticated = Melde dich an, um eine Erstattung einzureichen
+error-login-unavailable = Die Anmeldung ist nicht aktiviert
+error-invalid-login-state = Die Anmeldung konnte nicht abgeschlossen werden. Melde dich erneut an
+error-login-failed = Die Anmeldung ist fehlgeschlagen. Versuche es später erneut
+error-login-denied = Die Anmeldung wurde abgebrochen oder abgelehnt
+error-email-not-verified = Deine E-Mail-Adresse ist nicht bestätigt. Bestätige sie und melde dich erneut an
+error-admin-unauthenticated = Melde dich als Kassenwart an oder verwende ein API-Token
+error-forbidden = Du darfst keine Erstattungen verwalten
+error-unknown-declaration = Es gibt keine Erstattung mit dieser Referenz
@@ -0,0 +1,66 @@
+## Email subjects
+
+treasurer-subject = [DigiDecs] New declaration { $reference }: { $commission }
+submitter-subject = Your DigiDecs has been received! ({ $reference })
+
+## Labels shared by the emails
+
+label-reference = Reference
+label-name = Name
+label-email = Email
+label-address = Address
+label-value = Total amount
+label-what = What
+label-commission = For
+label-iban = Account number
+label-notes = Notes
+label-attachments = Attachments
+
+## Email to the treasurer
+
+treasurer-banner = New DigiDecs for { $commission }
+treasurer-download-links = The attachments were too large to email. They can be downloaded until { $expires }:
+
+## Confirmation email to the submitter
+
+submitter-banner = DigiDecs received!
+submitter-greeting = Hi, { $name }
+submitter-received = We have received your DigiDecs. If it has not been paid back to you within 7 working days, please contact the <a href="mailto:penningmeester@svsticky.nl">treasurer</a>.
+submitter-summary = This is what we have received. If anything is incorrect, please let the treasurer know as soon as possible.
+submitter-closing = With kind regards,<br/>The Board
+
+## API errors
+
+error-internal = Something went wrong on our side. Please try again later.
+error-invalid-iban = Invalid IBAN
+error-invalid-email = Invalid email address
+error-invalid-address = Invalid address
+error-missing-attachment = Missing attachments
+error-value-not-positive = The amount must be more than zero
+error-invalid-attachment = The attachment could not be read
+error-unknown-tracking-id = This DigiDecs does not exist. Start over again
+error-unknown-attachment-tracking-id = This attachment does not exist. Start over again
+error-digidecs-expired = This DigiDecs has expired. Start over again
+error-invalid-signature = This link is invalid
+error-link-expired = This link has expired
+error-unknown-attachment = This attachment does not exist
+error-body-too-large = The upload is too large
+error-validation = Not all fields are filled in correctly
+error-invalid-field = This field is missing or invalid
+error-invalid-form = The form could not be read
+error-invalid-content-range = The upload could not be continued
+error-upload-offset-mismatch = The upload was interrupted. Try again
+error-checksum-mismatch = The attachment was damaged during the upload. Try again
+error-rate-limited = Too many requests. Try again later
+error-bot-detected = Your submission was rejected
+error-invalid-challenge = The form has expired. Reload the page and try again
+error-submitted-too-fast = The form was submitted too quickly. Wait a moment and try again
+error-unauthenticated = Log in to submit a declaration
+error-login-unavailable = Logging in is not enabled
+error-invalid-login-state = The login could not be completed. Log in again
+error-login-failed = Logging in failed. Try again later
+error-login-denied = The login was cancelled or refused
+error-email-not-verified = Your email address has not been verified. Verify it and log in again
+error-admin-unauthenticated = Log in as treasurer or provide an API token
+error-forbidden = You are not allowed to manage declarations
+error-unknown-declaration = No declaration with that reference exists
@@ -0,0 +1,66 @@
+## Email subjects
+
+treasurer-subject = [DigiDecs] Nieuwe declaratie { $reference }: { $commission }
+submitter-subject = Je DigiDecs is ontvangen! ({ $reference })
+
+## Labels shared by the emails
+
+label-reference = Kenmerk
+label-name = Naam
+label-email = Email
+label-address = Adres
+label-value = Totaalbedrag
+label-what = Wat
+label-commission = Waarvoor
+label-iban = Rekeningnummer
+label-notes = Opmerkingen
+label-attachments = Bijlagen
+
+## Email to the treasurer
+
+treasurer-banner = Nieuwe DigiDecs voor { $commission }
+treasurer-download-links = De bijlagen waren te groot om te mailen. Ze zijn tot { $expires } te downloaden:
+
+## Confirmation email to the submitter
+
+submitter-banner = DigiDecs ontvangen!
+submitter-greeting = Hoi, { $name }
+submitter-received = We hebben je DigiDecs ontvangen. Als je je geld na 7 werkdagen nog niet hebt ontvangen, neem dan contact op met de <a href="mailto:penningmeester@svsticky.nl">penningmeester</a>.
+submitter-summary = Dit is wat we hebben ontvangen. Klopt er iets niet? Laat het de penningmeester dan zo snel mogelijk weten.
+submitter-closing = Met vriendelijke groet,<br/>Het bestuur
+
+## API errors
+
+error-internal = Er ging iets mis aan onze kant. Probeer het later opnieuw.
+error-invalid-iban = Ongeldig IBAN
+error-invalid-email = Ongeldig emailadres
+error-invalid-address = Ongeldig adres
+error-missing-attachment = Bijlagen ontbreken
+error-value-not-positive = Het bedrag moet meer dan nul zijn
+error-invalid-attachment = De bijlage kon niet gelezen worden
+error-unknown-tracking-id = Deze DigiDecs bestaat niet. Begin opnieuw
+error-unknown-attachment-tracking-id = Deze bijlage bestaat niet. Begin opnieuw
+error-digidecs-expired = Deze DigiDecs is verlopen. Begin opnieuw
+error-invalid-signature = Deze link is ongeldig
+error-link-expired = Deze link is verlopen
+error-unknown-attachment = Deze bijlage bestaat niet
+error-body-too-large = Het bestand is te groot
+error-validation = Niet alle velden zijn correct ingevuld
+error-invalid-field = Dit veld ontbreekt of is ongeldig
+error-invalid-form = Het formulier kon niet gelezen worden
+error-invalid-content-range = Het uploaden kon niet hervat worden
+error-upload-offset-mismatch = Het uploaden is onderbroken. Probeer het opnieuw
+error-checksum-mismatch = De bijlage is beschadigd tijdens het uploaden. Probeer het opnieuw
+error-rate-limited = Te veel verzoeken. Probeer het later opnieuw
+error-bot-detected = Je inzending is geweigerd
+error-invalid-challenge = Het formulier is verlopen. Herlaad de pagina en probeer het opnieuw
+error-submitted-too-fast = Het formulier is te snel verstuurd. Wacht even en probeer het opnieuw
+error-unauthenticated = Log in om een declaratie in te dienen
+error-login-unavailable = Inloggen is niet ingeschakeld
+error-invalid-login-state = Het inloggen kon niet worden afgerond. Log opnieuw in
+error-login-failed = Inloggen is mislukt. Probeer het later opnieuw
+error-login-denied = Het inloggen is geannuleerd of geweigerd
+error-email-not-verified = Je e-mailadres is niet geverifieerd. Verifieer het en log opnieuw in
+error-admin-unauthenticated = Log in als penningmeester of gebruik een API-token
+error-forbidden = Je mag geen declaraties beheren
+error-unknown-declaration = Er bestaat geen declaratie met dat kenmerk
@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+use fluent_bundle::concurrent::FluentBundle;
+use fluent_bundle::{FluentArgs, FluentResource};
+use serde::{Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+use tracing::{debug, warn};
+use unic_langid::LanguageIdentifier;
+
+pub use helper::TranslateHelper;
+
+mod helper;
+
+/// The built-in message catalogs, by language tag.
+/// A file `<tag>.ftl` in the locales directory overrides a built-in catalog or adds a new locale.
+static BUILTIN_CATALOGS: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.ftl")),
+    ("nl", include_str!("locales/nl.ftl")),
+    ("de", include_str!("locales/de.ftl")),
+];
+
+#[derive(Debug, Error)]
+pub enum CatalogError {
+    #[error("Failed to read catalog: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid language tag {0}")]
+    LanguageTag(String),
+    #[error("Invalid message catalog for {0}: {1}")]
+    Syntax(String, String),
+    #[error("No catalog exists for the default locale {0}")]
+    MissingDefault(Locale),
+}
+
+/// A language tag, e.g. `nl` or `en-US`.
+/// Whether a translation exists is determined by the [Catalog], see [Catalog::resolve].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct Locale(String);
+
+impl Locale {
+    pub fn new(tag: &str) -> Self {
+        Self(tag.trim().to_lowercase())
+    }
+
+    /// The primary language subtag, e.g. `en` for `en-US`
+    fn language(&self) -> &str {
+        self.0.split(['-', '_']).next().unwrap_or_default()
+    }
+}
+
+impl Display for Locale {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Locale {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Older clients send `Nl` and `En`, which normalize to the language tags
+        Ok(Self::new(&String::deserialize(deserializer)?))
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::new("nl")
+    }
+}
+
+/// All message catalogs, used for everything shown to users:
+/// email subjects, email templates and API errors.
+pub struct Catalog {
+    bundles: HashMap<Locale, FluentBundle<FluentResource>>,
+    default: Locale,
+}
+
+impl Catalog {
+    /// Load the built-in catalogs, and the catalogs in `dir`.
+    ///
+    /// # Errors
+    ///
+    /// If a catalog could not be read or parsed, or if there is no catalog for `default`
+    pub fn load(dir: Option<&Path>, default: Locale) -> Result<Self, CatalogError> {
+        let mut sources = BUILTIN_CATALOGS
+            .iter()
+            .map(|(tag, source)| (Locale::new(tag), source.to_string()))
+            .collect::<HashMap<_, _>>();
+
+        if let Some(dir) = dir {
+            for entry in std::fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("ftl") {
+                    continue;
+                }
+
+                let Some(tag) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                    continue;
+                };
+
+                debug!("Loading message catalog {}", path.display());
+                sources.insert(Locale::new(tag), std::fs::read_to_string(&path)?);
+            }
+        }
+
+        let Some(default_source) = sources.get(&default) else {
+            return Err(CatalogError::MissingDefault(default));
+        };
+        let ids = message_ids(default_source);
+
+        let bundles = sources
+            .into_iter()
+            .map(|(locale, source)| {
+                let bundle = create_bundle(&locale, source)?;
+                Ok((locale, bundle))
+            })
+            .collect::<Result<HashMap<_, _>, CatalogError>>()?;
+
+        // Messages missing from other catalogs fall back to the default locale
+        for (locale, bundle) in &bundles {
+            for id in ids.iter().filter(|id| !bundle.has_message(id)) {
+                warn!("Message {id} is missing for locale {locale}, falling back to {default}");
+            }
+        }
+
+        Ok(Self { bundles, default })
+    }
+
+    /// The locales for which a catalog exists
+    pub fn locales(&self) -> impl Iterator<Item = &Locale> {
+        self.bundles.keys()
+    }
+
+    pub fn default_locale(&self) -> &Locale {
+        &self.default
+    }
+
+    /// The locale with a catalog closest to `locale`.
+    /// Tries the locale itself, then its primary language, then falls back to the default locale.
+    pub fn resolve(&self, locale: &Locale) -> Locale {
+        [locale.clone(), Locale::new(locale.language())]
+            .into_iter()
+            .find(|candidate| self.bundles.contains_key(candidate))
+            .unwrap_or_else(|| self.default.clone())
+    }
+
+    /// The preferred locale from an `Accept-Language` header for which a catalog exists.
+    /// Returns `None` if there is no catalog for any of the languages in the header.
+    pub fn negotiate(&self, accept_language: &str) -> Option<Locale> {
+        let mut languages = accept_language
+            .split(',')
+            .filter_map(|language| {
+                let mut parts = language.split(';');
+                let tag = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+
+                (!tag.is_empty() && tag != "*" && quality > 0.0)
+                    .then(|| (Locale::new(tag), quality))
+            })
+            .collect::<Vec<_>>();
+
+        // Stable, so languages with the same quality keep their order
+        languages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        languages.into_iter().find_map(|(locale, _)| {
+            [locale.clone(), Locale::new(locale.language())]
+                .into_iter()
+                .find(|candidate| self.bundles.contains_key(candidate))
+        })
+    }
+
+    /// Format the message with the provided ID.
+    /// If the message does not exist in the catalog of the locale,
+    /// the catalog of the default locale is used.
+    /// Returns `None` if the message does not exist in either.
+    pub fn format(&self, locale: &Locale, id: &str, args: Option<&FluentArgs>) -> Option<String> {
+        [self.resolve(locale), self.default.clone()]
+            .iter()
+            .filter_map(|locale| self.bundles.get(locale))
+            .find_map(|bundle| {
+                let pattern = bundle.get_message(id)?.value()?;
+
+                let mut errors = vec![];
+                let formatted = bundle.format_pattern(pattern, args, &mut errors);
+                if !errors.is_empty() {
+                    warn!("Errors formatting message {id}: {errors:?}");
+                }
+
+                Some(formatted.into_owned())
+            })
+    }
+}
+
+fn create_bundle(
+    locale: &Locale,
+    source: String,
+) -> Result<FluentBundle<FluentResource>, CatalogError> {
+    let langid = locale
+        .0
+        .parse::<LanguageIdentifier>()
+        .map_err(|_| CatalogError::LanguageTag(locale.to_string()))?;
+
+    let resource = FluentResource::try_new(source)
+        .map_err(|(_, errors)| CatalogError::Syntax(locale.to_string(), format!("{errors:?}")))?;
+
+    let mut bundle = FluentBundle::new_concurrent(vec![langid]);
+    // Messages end up in HTML emails, in which the Unicode isolation marks only get in the way
+    bundle.set_use_isolating(false);
+    bundle
+        .add_resource(resource)
+        .map_err(|errors| CatalogError::Syntax(locale.to_string(), format!("{errors:?}")))?;
+
+    Ok(bundle)
+}
+
+fn message_ids(source: &str) -> Vec<String> {
+    source
+        .lines()
+        .filter(|line| line.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, _)| id.trim().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn catalog() -> Catalog {
+        Catalog::load(None, Locale::new("nl")).unwrap()
+    }
+
+    #[test]
+    fn resolve_locale() {
+        let catalog = catalog();
+        assert_eq!(catalog.resolve(&Locale::new("En")), Locale::new("en"));
+        assert_eq!(catalog.resolve(&Locale::new("en-US")), Locale::new("en"));
+        assert_eq!(catalog.resolve(&Locale::new("fr")), Locale::new("nl"));
+    }
+
+    #[test]
+    fn negotiate_locale() {
+        let catalog = catalog();
+        assert_eq!(
+            catalog.negotiate("en-US,en;q=0.9,nl;q=0.8"),
+            Some(Locale::new("en"))
+        );
+        assert_eq!(
+            catalog.negotiate("fr;q=0.9, nl;q=0.5, en;q=0.7"),
+            Some(Locale::new("en"))
+        );
+        assert_eq!(catalog.negotiate("fr, *;q=0.5"), None);
+        assert_eq!(catalog.negotiate(""), None);
+    }
+
+    #[test]
+    fn format_with_args() {
+        let catalog = catalog();
+        let mut args = FluentArgs::new();
+        args.set("name", "Jan");
+
+        assert_eq!(
+            catalog
+                .format(&Locale::new("en"), "submitter-greeting", Some(&args))
+                .as_deref(),
+            Some("Hi, Jan")
+        );
+        assert_eq!(
+            catalog
+                .format(&Locale::new("fr"), "submitter-greeting", Some(&args))
+                .as_deref(),
+            Some("Hoi, Jan")
+        );
+        assert_eq!(catalog.format(&Locale::new("en"), "unknown", None), None);
+    }
+
+    #[test]
+    fn builtin_catalogs_complete() {
+        let ids = message_ids(BUILTIN_CATALOGS[0].1);
+        assert!(!ids.is_empty());
+
+        for (tag, source) in BUILTIN_CATALOGS {
+            assert_eq!(message_ids(source), ids, "Catalog {tag} differs");
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::args::AppArgs;
+use crate::args::{AppArgs, Command};
 use crate::file::{AppConfig, DataFile};
 use clap::Parser;
 use tracing::{debug, trace};
@@ -10,7 +10,13 @@ use tracing_subscriber::EnvFilter;
 mod args;
 mod email;
 mod file;
+mod i18n;
+mod oidc;
+mod render;
 mod server;
+mod signing;
+mod storage;
+mod tokens;
 
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
@@ -21,8 +27,13 @@ async fn main() -> color_eyre::Result<()> {
 
     let args = AppArgs::parse();
     let config = AppConfig::try_read(&args.config, true).await?;
+    email::routing::validate(&config.routing)?;
 
-    server::run_server(config, args).await
+    match &args.command {
+        Some(Command::Render(render_args)) => render::render(&config, render_args).await,
+        Some(Command::Token(command)) => tokens::manage(&config, command).await,
+        None => server::run_server(config, args).await,
+    }
 }
 
 fn init_tracing() -> color_eyre::Result<()> {
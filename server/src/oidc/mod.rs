@@ -0,0 +1,541 @@
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::Rng;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use time::OffsetDateTime;
+use tokio::sync::OnceCell;
+use tracing::{info, trace};
+
+use crate::file::OidcConfig;
+
+#[derive(Debug, Error)]
+pub enum OidcError {
+    #[error("Request to the issuer failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+    #[error("The issuer must use https, unless it runs on this machine")]
+    InsecureIssuer,
+    #[error("The discovered issuer {0} does not match the configured issuer")]
+    IssuerMismatch(String),
+    #[error("The issuer denied the login: {0}")]
+    Denied(String),
+    #[error("Token request failed: {0}")]
+    Token(String),
+    #[error("Invalid ID token: {0}")]
+    IdToken(&'static str),
+    #[error("The email address is not verified by the issuer")]
+    EmailNotVerified,
+}
+
+/// The endpoints of the issuer, from its discovery document
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+}
+
+/// A login in progress. Kept by the browser until the issuer redirects back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    /// Path in the form to return to after logging in
+    pub return_to: String,
+}
+
+/// The identity of a logged in member, as verified by the issuer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    pub name: String,
+    pub email: String,
+    #[serde(default)]
+    pub address: Option<String>,
+    /// The roles from the configured roles claim
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Client for logging in with the authorization code flow and PKCE
+pub struct OidcClient {
+    config: OidcConfig,
+    redirect_uri: String,
+    http: reqwest::Client,
+    metadata: OnceCell<ProviderMetadata>,
+}
+
+impl OidcClient {
+    /// Create a client, redirecting back to `redirect_uri` after logging in.
+    /// The issuer is discovered on first use.
+    ///
+    /// # Errors
+    ///
+    /// If the issuer URL is invalid, or does not use https while not running on this machine
+    pub fn new(config: OidcConfig, redirect_uri: String) -> Result<Self, OidcError> {
+        let issuer = Url::parse(&config.issuer)?;
+        let local = match issuer.host() {
+            Some(url::Host::Domain(host)) => host == "localhost",
+            Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+            Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+            None => false,
+        };
+        if issuer.scheme() != "https" && !local {
+            return Err(OidcError::InsecureIssuer);
+        }
+
+        Ok(Self {
+            config,
+            redirect_uri,
+            http: reqwest::Client::new(),
+            metadata: OnceCell::new(),
+        })
+    }
+
+    async fn metadata(&self) -> Result<&ProviderMetadata, OidcError> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!(
+                    "{}/.well-known/openid-configuration",
+                    self.config.issuer.trim_end_matches('/')
+                );
+                trace!("Discovering OpenID provider at {url}");
+
+                let metadata = self
+                    .http
+                    .get(url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json::<ProviderMetadata>()
+                    .await?;
+
+                if metadata.issuer.trim_end_matches('/') != self.config.issuer.trim_end_matches('/')
+                {
+                    return Err(OidcError::IssuerMismatch(metadata.issuer));
+                }
+
+                info!("Discovered OpenID provider {}", metadata.issuer);
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// The URL to send the browser to for logging in, and the login to remember until it returns
+    ///
+    /// # Errors
+    ///
+    /// If the issuer could not be discovered
+    pub async fn authorization_url(
+        &self,
+        return_to: String,
+    ) -> Result<(String, LoginRequest), OidcError> {
+        let metadata = self.metadata().await?;
+        let login = LoginRequest {
+            state: random_string(32),
+            nonce: random_string(32),
+            code_verifier: random_string(64),
+            return_to,
+        };
+
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", &self.config.client_id),
+                ("redirect_uri", &self.redirect_uri),
+                ("scope", &self.config.scopes.join(" ")),
+                ("state", &login.state),
+                ("nonce", &login.nonce),
+                ("code_challenge", &code_challenge(&login.code_verifier)),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+
+        Ok((url.to_string(), login))
+    }
+
+    /// Exchange the authorization code the issuer redirected back with for the identity of the member
+    ///
+    /// # Errors
+    ///
+    /// If the token request fails, or the ID token is not valid for this login
+    pub async fn exchange(&self, login: &LoginRequest, code: &str) -> Result<Identity, OidcError> {
+        let metadata = self.metadata().await?;
+
+        let mut request = self.http.post(&metadata.token_endpoint).form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &self.redirect_uri),
+            ("client_id", &self.config.client_id),
+            ("code_verifier", &login.code_verifier),
+        ]);
+        if let Some(secret) = &self.config.client_secret {
+            request = request.basic_auth(&self.config.client_id, Some(secret));
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(OidcError::Token(response.text().await?));
+        }
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            id_token: String,
+        }
+        let token = response.json::<TokenResponse>().await?;
+
+        verify_id_token(
+            &token.id_token,
+            &metadata.issuer,
+            &self.config.client_id,
+            &login.nonce,
+            &self.config.roles_claim,
+            OffsetDateTime::now_utc(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct AddressClaim {
+    formatted: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    iss: String,
+    aud: Audience,
+    azp: Option<String>,
+    exp: i64,
+    nonce: Option<String>,
+    name: Option<String>,
+    given_name: Option<String>,
+    family_name: Option<String>,
+    email: Option<String>,
+    email_verified: Option<bool>,
+    address: Option<AddressClaim>,
+}
+
+/// Verify the claims of an ID token and extract the identity from it.
+///
+/// The signature is not verified: the token was received directly from the token endpoint
+/// over a TLS connection to the issuer, which OpenID Connect Core (3.1.3.7) allows instead.
+fn verify_id_token(
+    id_token: &str,
+    issuer: &str,
+    client_id: &str,
+    nonce: &str,
+    roles_claim: &str,
+    now: OffsetDateTime,
+) -> Result<Identity, OidcError> {
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or(OidcError::IdToken("not a JWT"))?;
+    let payload = BASE64_URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .map_err(|_| OidcError::IdToken("invalid base64"))?;
+    let raw = serde_json::from_slice::<serde_json::Value>(&payload)
+        .map_err(|_| OidcError::IdToken("invalid claims"))?;
+    let roles = roles(&raw, roles_claim);
+    let claims =
+        serde_json::from_value::<Claims>(raw).map_err(|_| OidcError::IdToken("invalid claims"))?;
+
+    if claims.iss != issuer {
+        return Err(OidcError::IdToken("issued by another issuer"));
+    }
+    let audience_ok = match &claims.aud {
+        Audience::One(aud) => aud == client_id,
+        Audience::Many(auds) => {
+            auds.iter().any(|aud| aud == client_id)
+                && (auds.len() == 1 || claims.azp.as_deref() == Some(client_id))
+        }
+    };
+    if !audience_ok {
+        return Err(OidcError::IdToken("issued for another client"));
+    }
+    if claims.exp <= now.unix_timestamp() {
+        return Err(OidcError::IdToken("expired"));
+    }
+    if claims.nonce.as_deref() != Some(nonce) {
+        return Err(OidcError::IdToken("nonce does not match"));
+    }
+
+    // Issuers that do not verify email addresses commonly omit the claim altogether
+    if claims.email_verified == Some(false) {
+        return Err(OidcError::EmailNotVerified);
+    }
+    let email = claims.email.ok_or(OidcError::IdToken("no email address"))?;
+
+    let name = claims
+        .name
+        .or_else(|| match (claims.given_name, claims.family_name) {
+            (Some(given), Some(family)) => Some(format!("{given} {family}")),
+            (given, family) => given.or(family),
+        })
+        .unwrap_or_else(|| email.clone());
+
+    Ok(Identity {
+        name,
+        email,
+        address: claims.address.and_then(|address| address.formatted),
+        roles,
+    })
+}
+
+/// The roles in the claim at the dot separated `path`, which holds a role or a list of roles
+fn roles(claims: &serde_json::Value, path: &str) -> Vec<String> {
+    let claim = path
+        .split('.')
+        .try_fold(claims, |value, key| value.get(key));
+
+    match claim {
+        Some(serde_json::Value::String(role)) => vec![role.clone()],
+        Some(serde_json::Value::Array(roles)) => roles
+            .iter()
+            .filter_map(|role| role.as_str().map(str::to_string))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(rand::distributions::Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+/// The PKCE `S256` challenge for a code verifier
+fn code_challenge(verifier: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// What the mock issuer learned from the authorization request
+    #[derive(Default)]
+    struct Authorized {
+        code_challenge: String,
+        nonce: String,
+    }
+
+    fn id_token(claims: serde_json::Value) -> String {
+        let encode = |value: &serde_json::Value| BASE64_URL_SAFE_NO_PAD.encode(value.to_string());
+        format!(
+            "{}.{}.signature",
+            encode(&serde_json::json!({"alg": "RS256"})),
+            encode(&claims)
+        )
+    }
+
+    /// Start an issuer on a local port, returning its URL
+    fn mock_issuer(authorized: Arc<Mutex<Authorized>>) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+        let discovery = {
+            let issuer = issuer.clone();
+            move || {
+                let issuer = issuer.clone();
+                async move {
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "issuer": issuer,
+                        "authorization_endpoint": format!("{issuer}/authorize"),
+                        "token_endpoint": format!("{issuer}/token"),
+                    }))
+                }
+            }
+        };
+        let token = {
+            let issuer = issuer.clone();
+            move |form: web::Form<HashMap<String, String>>| {
+                let issuer = issuer.clone();
+                let authorized = authorized.clone();
+                async move {
+                    let authorized = authorized.lock().unwrap();
+                    let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+                    if form.get("code").map(String::as_str) != Some("code")
+                        || code_challenge(&verifier) != authorized.code_challenge
+                    {
+                        return HttpResponse::BadRequest()
+                            .json(serde_json::json!({"error": "invalid_grant"}));
+                    }
+
+                    HttpResponse::Ok().json(serde_json::json!({
+                        "access_token": "access",
+                        "token_type": "Bearer",
+                        "id_token": id_token(serde_json::json!({
+                            "iss": issuer,
+                            "aud": "digidecs",
+                            "exp": OffsetDateTime::now_utc().unix_timestamp() + 300,
+                            "nonce": authorized.nonce,
+                            "given_name": "Jan",
+                            "family_name": "Jansen",
+                            "email": "jan@example.com",
+                            "email_verified": true,
+                            "realm_access": {"roles": ["treasurer"]},
+                        })),
+                    }))
+                }
+            }
+        };
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(discovery.clone()),
+                )
+                .route("/token", web::post().to(token.clone()))
+        })
+        .workers(1)
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_web::rt::spawn(server);
+
+        issuer
+    }
+
+    fn client(issuer: &str) -> OidcClient {
+        OidcClient::new(
+            OidcConfig {
+                issuer: issuer.to_string(),
+                client_id: "digidecs".to_string(),
+                roles_claim: "realm_access.roles".to_string(),
+                ..OidcConfig::default()
+            },
+            "https://digidecs.example.com/api/auth/callback".to_string(),
+        )
+        .unwrap()
+    }
+
+    #[actix_web::test]
+    async fn login_with_mock_issuer() {
+        let authorized = Arc::new(Mutex::new(Authorized::default()));
+        let client = client(&mock_issuer(authorized.clone()));
+
+        let (url, login) = client.authorization_url("/".to_string()).await.unwrap();
+        let url = Url::parse(&url).unwrap();
+        let params = url.query_pairs().collect::<HashMap<_, _>>();
+        assert_eq!(params["client_id"], "digidecs");
+        assert_eq!(params["state"], login.state.as_str());
+        assert_eq!(params["code_challenge_method"], "S256");
+
+        *authorized.lock().unwrap() = Authorized {
+            code_challenge: params["code_challenge"].to_string(),
+            nonce: params["nonce"].to_string(),
+        };
+
+        let identity = client.exchange(&login, "code").await.unwrap();
+        assert_eq!(
+            identity,
+            Identity {
+                name: "Jan Jansen".to_string(),
+                email: "jan@example.com".to_string(),
+                address: None,
+                roles: vec!["treasurer".to_string()],
+            }
+        );
+
+        // Without the right code verifier the issuer refuses
+        let wrong = LoginRequest {
+            code_verifier: random_string(64),
+            ..login
+        };
+        assert!(matches!(
+            client.exchange(&wrong, "code").await,
+            Err(OidcError::Token(_))
+        ));
+    }
+
+    #[test]
+    fn id_token_claims() {
+        let now = OffsetDateTime::now_utc();
+        let claims = |extra: serde_json::Value| {
+            let mut claims = serde_json::json!({
+                "iss": "https://login.example.com",
+                "aud": ["digidecs"],
+                "exp": now.unix_timestamp() + 60,
+                "nonce": "nonce",
+                "name": "Jan Jansen",
+                "email": "jan@example.com",
+            });
+            claims
+                .as_object_mut()
+                .unwrap()
+                .extend(extra.as_object().unwrap().clone());
+            id_token(claims)
+        };
+        let verify = |token: &str| {
+            verify_id_token(
+                token,
+                "https://login.example.com",
+                "digidecs",
+                "nonce",
+                "roles",
+                now,
+            )
+        };
+
+        assert!(verify(&claims(serde_json::json!({})))
+            .unwrap()
+            .roles
+            .is_empty());
+        assert_eq!(
+            verify(&claims(serde_json::json!({"roles": "treasurer"})))
+                .unwrap()
+                .roles,
+            vec!["treasurer".to_string()]
+        );
+        assert!(verify(&claims(serde_json::json!({"nonce": "other"}))).is_err());
+        assert!(verify(&claims(serde_json::json!({"aud": "other"}))).is_err());
+        assert!(verify(&claims(serde_json::json!({"iss": "https://evil.example"}))).is_err());
+        assert!(verify(&claims(
+            serde_json::json!({"exp": now.unix_timestamp() - 1})
+        ))
+        .is_err());
+        assert!(matches!(
+            verify(&claims(serde_json::json!({"email_verified": false}))),
+            Err(OidcError::EmailNotVerified)
+        ));
+    }
+
+    #[test]
+    fn requires_https() {
+        let new = |issuer: &str| {
+            OidcClient::new(
+                OidcConfig {
+                    issuer: issuer.to_string(),
+                    ..OidcConfig::default()
+                },
+                String::new(),
+            )
+        };
+
+        assert!(new("https://login.example.com").is_ok());
+        assert!(new("http://127.0.0.1:8081").is_ok());
+        assert!(matches!(
+            new("http://login.example.com"),
+            Err(OidcError::InsecureIssuer)
+        ));
+    }
+}
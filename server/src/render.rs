@@ -0,0 +1,130 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use tracing::info;
+
+use crate::args::{RenderArgs, TemplateName};
+use crate::email::routing::route;
+use crate::email::template::{
+    render_submitter, render_treasurer, SubmitterData, Templates, TreasurerData,
+};
+use crate::email::{submitter_email, treasurer_email, SubmitterEmailData, TreasurerEmailData};
+use crate::file::AppConfig;
+use crate::i18n::Catalog;
+use crate::storage::Reference;
+
+/// Sample data supplied with `--sample`
+#[derive(Deserialize)]
+struct SampleData {
+    #[serde(default = "TreasurerData::sample")]
+    treasurer: TreasurerData,
+    #[serde(default = "SubmitterData::sample")]
+    submitter: SubmitterData,
+}
+
+impl Default for SampleData {
+    fn default() -> Self {
+        Self {
+            treasurer: TreasurerData::sample(),
+            submitter: SubmitterData::sample(),
+        }
+    }
+}
+
+/// Render the templates with sample data in every locale,
+/// writing `<template>.<locale>.html` and `<template>.<locale>.eml` to the output directory.
+pub async fn render(config: &AppConfig, args: &RenderArgs) -> color_eyre::Result<()> {
+    let mut sample = match &args.sample {
+        Some(path) => serde_json::from_str(&tokio::fs::read_to_string(path).await?)?,
+        None => SampleData::default(),
+    };
+
+    let catalog = Arc::new(Catalog::load(
+        config.locales_dir.as_deref(),
+        config.default_locale.clone(),
+    )?);
+    let templates = Templates::load(config.templates_dir.as_deref(), catalog.clone())?;
+
+    tokio::fs::create_dir_all(&args.out).await?;
+
+    let mut locales = catalog.locales().cloned().collect::<Vec<_>>();
+    locales.sort_by_key(|locale| locale.to_string());
+
+    let wants = |name: TemplateName| args.template.is_none_or(|template| template == name);
+
+    for locale in locales {
+        if wants(TemplateName::Treasurer) {
+            let data = &mut sample.treasurer;
+            data.locale = locale.clone();
+
+            let body = render_treasurer(&templates, data)?;
+            let msg = treasurer_email(
+                &config.smtp,
+                TreasurerEmailData {
+                    catalog: &catalog,
+                    locale: &locale,
+                    reference: &data.reference.parse::<Reference>()?,
+                    recipients: &route(
+                        config,
+                        &data.commission,
+                        data.value.parse().unwrap_or_default(),
+                    ),
+                    body: &body,
+                    reply_to_name: &data.name,
+                    reply_to_email: &data.email,
+                    commission: &data.commission,
+                    attachments: vec![],
+                },
+            )?;
+
+            write(
+                &args.out,
+                &format!("treasurer.{locale}"),
+                &body,
+                &msg.formatted(),
+            )
+            .await?;
+        }
+
+        if wants(TemplateName::Submitter) {
+            let data = &mut sample.submitter;
+            data.locale = locale.clone();
+
+            let body = render_submitter(&templates, data)?;
+            // The sample submitter is the person in the sample treasurer data
+            let msg = submitter_email(
+                &config.smtp,
+                SubmitterEmailData {
+                    catalog: &catalog,
+                    reference: &data.reference.parse::<Reference>()?,
+                    to_email: &sample.treasurer.email,
+                    name: &sample.treasurer.name,
+                    body: body.clone(),
+                    locale: &locale,
+                    commission: &data.commission,
+                    attachments: vec![],
+                },
+            )?;
+
+            write(
+                &args.out,
+                &format!("submitter.{locale}"),
+                &body,
+                &msg.formatted(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn write(out: &Path, name: &str, html: &str, eml: &[u8]) -> std::io::Result<()> {
+    let html_path = out.join(format!("{name}.html"));
+    tokio::fs::write(&html_path, html).await?;
+    tokio::fs::write(out.join(format!("{name}.eml")), eml).await?;
+
+    info!("Rendered {}", html_path.display());
+    Ok(())
+}
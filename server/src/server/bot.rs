@@ -0,0 +1,334 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::file::BotProtectionConfig;
+use crate::server::types::{Error, WResult};
+use crate::signing::Signer;
+
+/// A challenge handed out to the form, proving when the form was loaded
+/// and, if enabled, asking for a proof of work
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Challenge {
+    /// The signed challenge, to be returned with the submission
+    pub token: String,
+    /// Find a `solution` for which the SHA-256 of `<token>:<solution>` starts with this many zero bits
+    pub difficulty: u8,
+    /// The submission is rejected if made sooner than this after the challenge was issued
+    pub min_fill_secs: u64,
+}
+
+/// A challenge returned with a submission
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ChallengeSolution {
+    pub token: String,
+    /// The solution to the proof of work. May be empty if no proof of work is asked.
+    #[serde(default)]
+    pub solution: String,
+}
+
+/// The parts of a challenge token, `<issued at>.<nonce>.<difficulty>.<signature>`
+struct Token<'a> {
+    issued_at: OffsetDateTime,
+    nonce: &'a str,
+    difficulty: u8,
+}
+
+/// Checks protecting the form against bots, without an external captcha service
+pub struct BotProtection {
+    config: BotProtectionConfig,
+    signer: Signer,
+    /// Nonces of challenges that have been used, with when they expire.
+    /// A challenge can only be used for a single submission.
+    used: Mutex<HashMap<String, OffsetDateTime>>,
+}
+
+impl BotProtection {
+    pub fn new(config: BotProtectionConfig, signer: Signer) -> Self {
+        Self {
+            config,
+            signer,
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether submissions must include a solved challenge
+    pub fn requires_challenge(&self) -> bool {
+        self.config.min_fill_secs.is_some() || self.config.proof_of_work_bits.is_some()
+    }
+
+    pub fn issue(&self) -> Challenge {
+        self.issue_at(OffsetDateTime::now_utc())
+    }
+
+    fn issue_at(&self, now: OffsetDateTime) -> Challenge {
+        let nonce = rand::thread_rng()
+            .sample_iter(rand::distributions::Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>();
+        let difficulty = self.config.proof_of_work_bits.unwrap_or(0);
+
+        let message = format!("{}.{nonce}.{difficulty}", now.unix_timestamp());
+        let signature = self.signer.sign(&signed_message(&message));
+
+        Challenge {
+            token: format!("{message}.{signature}"),
+            difficulty,
+            min_fill_secs: self.config.min_fill_secs.unwrap_or(0),
+        }
+    }
+
+    /// Check the honeypot field and, if required, the challenge, and mark the challenge as used.
+    /// Checking and marking happen under one lock, so concurrent submissions
+    /// with the same challenge cannot both pass.
+    /// Call [Self::release] if the submission is rejected afterwards.
+    ///
+    /// # Errors
+    ///
+    /// If the submission looks like it was made by a bot
+    pub fn verify_and_consume(
+        &self,
+        honeypot: Option<&str>,
+        challenge: Option<&ChallengeSolution>,
+    ) -> WResult<()> {
+        self.verify_and_consume_at(honeypot, challenge, OffsetDateTime::now_utc())
+    }
+
+    fn verify_and_consume_at(
+        &self,
+        honeypot: Option<&str>,
+        challenge: Option<&ChallengeSolution>,
+        now: OffsetDateTime,
+    ) -> WResult<()> {
+        if self.config.honeypot && honeypot.is_some_and(|value| !value.trim().is_empty()) {
+            return Err(Error::BotDetected);
+        }
+
+        if !self.requires_challenge() {
+            return Ok(());
+        }
+
+        let challenge = challenge.ok_or(Error::InvalidChallenge)?;
+        let token = self
+            .parse(&challenge.token)
+            .ok_or(Error::InvalidChallenge)?;
+
+        let validity = time::Duration::seconds(self.config.challenge_validity_secs as i64);
+        if token.issued_at + validity < now || token.issued_at > now {
+            return Err(Error::InvalidChallenge);
+        }
+
+        if let Some(bits) = self.config.proof_of_work_bits {
+            if token.difficulty < bits || !solves(&challenge.token, &challenge.solution, bits) {
+                return Err(Error::InvalidChallenge);
+            }
+        }
+
+        if let Some(min_fill_secs) = self.config.min_fill_secs {
+            let filled_in = now - token.issued_at;
+            let min_fill = time::Duration::seconds(min_fill_secs as i64);
+            if filled_in < min_fill {
+                let remaining = Duration::try_from(min_fill - filled_in).unwrap_or_default();
+                return Err(Error::SubmittedTooFast(remaining));
+            }
+        }
+
+        let mut used = self.used.lock().unwrap();
+        used.retain(|_, expires_at| *expires_at > now);
+        match used.entry(token.nonce.to_string()) {
+            Entry::Occupied(_) => Err(Error::InvalidChallenge),
+            Entry::Vacant(entry) => {
+                entry.insert(token.issued_at + validity);
+                Ok(())
+            }
+        }
+    }
+
+    /// Make a challenge accepted by [Self::verify_and_consume] usable again,
+    /// for when the submission is rejected for another reason
+    pub fn release(&self, challenge: Option<&ChallengeSolution>) {
+        if let Some(token) = challenge.and_then(|challenge| self.parse(&challenge.token)) {
+            self.used.lock().unwrap().remove(token.nonce);
+        }
+    }
+
+    fn parse<'a>(&self, token: &'a str) -> Option<Token<'a>> {
+        let (message, signature) = token.rsplit_once('.')?;
+        if !self.signer.verify(&signed_message(message), signature) {
+            return None;
+        }
+
+        let mut parts = message.splitn(3, '.');
+        let issued_at = OffsetDateTime::from_unix_timestamp(parts.next()?.parse().ok()?).ok()?;
+        let nonce = parts.next()?;
+        let difficulty = parts.next()?.parse().ok()?;
+
+        Some(Token {
+            issued_at,
+            nonce,
+            difficulty,
+        })
+    }
+}
+
+/// Signatures of challenges must not be usable as signatures of other values
+fn signed_message(message: &str) -> String {
+    format!("challenge/{message}")
+}
+
+/// Whether the SHA-256 of `<token>:<solution>` starts with `bits` zero bits
+fn solves(token: &str, solution: &str, bits: u8) -> bool {
+    let hash = Sha256::digest(format!("{token}:{solution}").as_bytes());
+
+    let mut remaining = u32::from(bits);
+    for byte in hash {
+        if remaining == 0 {
+            return true;
+        }
+        let zeros = byte.leading_zeros().min(remaining);
+        if zeros < remaining.min(8) {
+            return false;
+        }
+        remaining -= zeros;
+    }
+
+    remaining == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn protection(min_fill_secs: Option<u64>, proof_of_work_bits: Option<u8>) -> BotProtection {
+        BotProtection::new(
+            BotProtectionConfig {
+                honeypot: true,
+                min_fill_secs,
+                proof_of_work_bits,
+                challenge_validity_secs: 3600,
+            },
+            Signer::new(b"secret"),
+        )
+    }
+
+    fn solve(challenge: &Challenge) -> ChallengeSolution {
+        let solution = (0u64..)
+            .find(|n| solves(&challenge.token, &n.to_string(), challenge.difficulty))
+            .unwrap();
+        ChallengeSolution {
+            token: challenge.token.clone(),
+            solution: solution.to_string(),
+        }
+    }
+
+    #[test]
+    fn honeypot() {
+        let protection = protection(None, None);
+        assert!(protection.verify_and_consume(None, None).is_ok());
+        assert!(protection.verify_and_consume(Some(""), None).is_ok());
+        assert!(matches!(
+            protection.verify_and_consume(Some("https://spam.example"), None),
+            Err(Error::BotDetected)
+        ));
+    }
+
+    #[test]
+    fn min_fill_time() {
+        let protection = protection(Some(5), None);
+        // Tokens have a precision of seconds
+        let issued = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let challenge = protection.issue_at(issued);
+        let solution = Some(ChallengeSolution {
+            token: challenge.token,
+            solution: String::new(),
+        });
+
+        assert!(matches!(
+            protection.verify_and_consume_at(None, solution.as_ref(), issued + time::Duration::seconds(2)),
+            Err(Error::SubmittedTooFast(remaining)) if remaining.as_secs() == 3
+        ));
+        assert!(protection
+            .verify_and_consume_at(None, solution.as_ref(), issued + time::Duration::seconds(5))
+            .is_ok());
+        assert!(matches!(
+            protection.verify_and_consume_at(
+                None,
+                solution.as_ref(),
+                issued + time::Duration::hours(2)
+            ),
+            Err(Error::InvalidChallenge)
+        ));
+        assert!(matches!(
+            protection.verify_and_consume(None, None),
+            Err(Error::InvalidChallenge)
+        ));
+    }
+
+    #[test]
+    fn proof_of_work() {
+        let protection = protection(None, Some(8));
+        let challenge = protection.issue();
+        assert_eq!(challenge.difficulty, 8);
+
+        let solution = solve(&challenge);
+        assert!(protection.verify_and_consume(None, Some(&solution)).is_ok());
+
+        let wrong = (0u64..)
+            .map(|n| n.to_string())
+            .find(|n| !solves(&challenge.token, n, 8))
+            .unwrap();
+        assert!(matches!(
+            protection.verify_and_consume(
+                None,
+                Some(&ChallengeSolution {
+                    token: challenge.token.clone(),
+                    solution: wrong
+                })
+            ),
+            Err(Error::InvalidChallenge)
+        ));
+
+        // A challenge can only be used once, unless released
+        assert!(matches!(
+            protection.verify_and_consume(None, Some(&solution)),
+            Err(Error::InvalidChallenge)
+        ));
+        protection.release(Some(&solution));
+        assert!(protection.verify_and_consume(None, Some(&solution)).is_ok());
+    }
+
+    #[test]
+    fn tampered_token() {
+        let protection = protection(None, Some(8));
+        let challenge = protection.issue();
+        // Lowering the difficulty invalidates the signature
+        let token = challenge.token.replacen(".8.", ".0.", 1);
+
+        assert!(matches!(
+            protection.verify_and_consume(
+                None,
+                Some(&ChallengeSolution {
+                    token,
+                    solution: String::new()
+                })
+            ),
+            Err(Error::InvalidChallenge)
+        ));
+    }
+
+    #[test]
+    fn leading_zero_bits() {
+        // sha256("a:b") = 0x6783...
+        assert!(solves("a", "b", 1));
+        assert!(!solves("a", "b", 2));
+        assert!(solves("a", "b", 0));
+    }
+}
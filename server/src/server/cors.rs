@@ -0,0 +1,135 @@
+use actix_cors::Cors;
+use actix_web::http::header::{HeaderName, RETRY_AFTER};
+use actix_web::http::{Method, Uri};
+use thiserror::Error;
+
+use crate::file::ServerConfig;
+
+#[derive(Debug, Error)]
+pub enum CorsError {
+    #[error("Invalid CORS origin '{0}', expected e.g. 'https://example.com'")]
+    Origin(String),
+    #[error("Invalid CORS method '{0}'")]
+    Method(String),
+    #[error("Invalid CORS header '{0}'")]
+    Header(String),
+}
+
+/// The CORS policy of the API, validated at startup.
+/// A [Cors] middleware is built from it for every worker.
+#[derive(Debug, Clone)]
+pub enum CorsPolicy {
+    /// Any origin may use the API. Only for development.
+    Permissive,
+    Restricted {
+        origins: Vec<String>,
+        methods: Vec<Method>,
+        headers: Vec<HeaderName>,
+        max_age_secs: usize,
+    },
+}
+
+impl CorsPolicy {
+    /// The policy configured in `config`. If no origins are configured,
+    /// only the origin of the public URL is allowed.
+    ///
+    /// # Errors
+    ///
+    /// If an origin, method or header is invalid
+    pub fn new(config: &ServerConfig, permissive: bool) -> Result<Self, CorsError> {
+        if permissive {
+            return Ok(Self::Permissive);
+        }
+
+        let origins = if config.cors.allowed_origins.is_empty() {
+            vec![config.public_url()]
+        } else {
+            config.cors.allowed_origins.clone()
+        };
+
+        Ok(Self::Restricted {
+            origins: origins
+                .iter()
+                .map(|origin| parse_origin(origin).ok_or_else(|| CorsError::Origin(origin.clone())))
+                .collect::<Result<_, _>>()?,
+            methods: config
+                .cors
+                .allowed_methods
+                .iter()
+                .map(|method| {
+                    Method::from_bytes(method.to_uppercase().as_bytes())
+                        .map_err(|_| CorsError::Method(method.clone()))
+                })
+                .collect::<Result<_, _>>()?,
+            headers: config
+                .cors
+                .allowed_headers
+                .iter()
+                .map(|header| {
+                    HeaderName::from_bytes(header.as_bytes())
+                        .map_err(|_| CorsError::Header(header.clone()))
+                })
+                .collect::<Result<_, _>>()?,
+            max_age_secs: config.cors.max_age_secs,
+        })
+    }
+
+    pub fn build(&self) -> Cors {
+        match self {
+            Self::Permissive => Cors::permissive(),
+            Self::Restricted {
+                origins,
+                methods,
+                headers,
+                max_age_secs,
+            } => origins
+                .iter()
+                .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin))
+                .allowed_methods(methods.clone())
+                .allowed_headers(headers.clone())
+                // Clients need it to know when to retry after being rate limited
+                .expose_headers([RETRY_AFTER])
+                .supports_credentials()
+                .max_age(*max_age_secs),
+        }
+    }
+}
+
+/// The origin, `scheme://host[:port]`, of a URL
+fn parse_origin(url: &str) -> Option<String> {
+    let uri = url.parse::<Uri>().ok()?;
+    Some(format!("{}://{}", uri.scheme_str()?, uri.authority()?))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn origin_of_url() {
+        assert_eq!(
+            parse_origin("https://digidecs.example.com/"),
+            Some("https://digidecs.example.com".to_string())
+        );
+        assert_eq!(
+            parse_origin("http://localhost:5173/form"),
+            Some("http://localhost:5173".to_string())
+        );
+        assert_eq!(parse_origin("digidecs.example.com"), None);
+    }
+
+    #[test]
+    fn defaults_to_public_url() {
+        let config = ServerConfig {
+            domain: "digidecs.example.com".to_string(),
+            ..ServerConfig::default()
+        };
+
+        match CorsPolicy::new(&config, false).unwrap() {
+            CorsPolicy::Restricted { origins, .. } => {
+                assert_eq!(origins, vec!["https://digidecs.example.com".to_string()])
+            }
+            CorsPolicy::Permissive => panic!("Expected a restricted policy"),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::middleware::Next;
+use actix_web::{HttpMessage, HttpRequest};
+
+use crate::i18n::{Catalog, Locale};
+use crate::server::types::{Error, WRuntime};
+
+/// Middleware replacing the message of [Error] responses with a message in the locale of the request.
+///
+/// The locale is the one a handler stored in the request extensions with [set_locale],
+/// otherwise it is negotiated from the `Accept-Language` header.
+pub async fn localize_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody, String>>, actix_web::Error> {
+    let res = next.call(req).await?;
+
+    let Some(runtime) = res.request().app_data::<WRuntime>().cloned() else {
+        return Ok(res.map_into_left_body());
+    };
+    let Some(error) = res.response().error().and_then(|e| e.as_error::<Error>()) else {
+        return Ok(res.map_into_left_body());
+    };
+
+    let locale = request_locale(res.request(), &runtime.catalog);
+    let body = serde_json::to_string(&error.localized_body(&runtime.catalog, &locale))?;
+
+    Ok(res.map_body(|_, _| EitherBody::right(body)))
+}
+
+/// Set the locale in which errors for this request are reported,
+/// e.g. the locale the submitter chose in the form.
+pub fn set_locale(req: &HttpRequest, locale: Locale) {
+    req.extensions_mut().insert(locale);
+}
+
+fn request_locale(req: &HttpRequest, catalog: &Catalog) -> Locale {
+    if let Some(locale) = req.extensions().get::<Locale>() {
+        return catalog.resolve(locale);
+    }
+
+    req.headers()
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| catalog.negotiate(value))
+        .unwrap_or_else(|| catalog.default_locale().clone())
+}
@@ -0,0 +1,139 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use prometheus::{
+    exponential_buckets, Histogram, HistogramOpts, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+use time::OffsetDateTime;
+
+use crate::server::types::{Error, PendingDigidecs, WRuntime};
+
+/// The metrics exposed at `/metrics`, in the Prometheus text format
+pub struct Metrics {
+    registry: Registry,
+    /// Handled requests, by route and outcome.
+    /// The outcome is `ok`, or the [Error::code] of the error.
+    requests: IntCounterVec,
+    pub upload_size: Histogram,
+    pub smtp_send_duration: Histogram,
+    pending: IntGaugeVec,
+    pending_bytes: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("digidecs".to_string()), None)?;
+
+        let requests = IntCounterVec::new(
+            Opts::new("requests_total", "Handled requests by route and outcome"),
+            &["route", "outcome"],
+        )?;
+        let upload_size = Histogram::with_opts(
+            HistogramOpts::new("upload_size_bytes", "Size of uploaded attachments")
+                // 1 KiB up to 64 MiB
+                .buckets(exponential_buckets(1024.0, 4.0, 9)?),
+        )?;
+        let smtp_send_duration = Histogram::with_opts(HistogramOpts::new(
+            "smtp_send_duration_seconds",
+            "Time taken to deliver an email to the relay",
+        ))?;
+        let pending = IntGaugeVec::new(
+            Opts::new(
+                "pending_declarations",
+                "Declarations started but not yet completed, by whether they expired",
+            ),
+            &["state"],
+        )?;
+        let pending_bytes = IntGauge::new(
+            "pending_declarations_bytes",
+            "Approximate memory used by pending declarations and their attachments",
+        )?;
+
+        registry.register(Box::new(requests.clone()))?;
+        registry.register(Box::new(upload_size.clone()))?;
+        registry.register(Box::new(smtp_send_duration.clone()))?;
+        registry.register(Box::new(pending.clone()))?;
+        registry.register(Box::new(pending_bytes.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests,
+            upload_size,
+            smtp_send_duration,
+            pending,
+            pending_bytes,
+        })
+    }
+
+    /// Update the gauges describing the pending declarations
+    pub fn observe_pending(&self, pending: &[PendingDigidecs]) {
+        let now = OffsetDateTime::now_utc();
+        let expired = pending.iter().filter(|d| d.expires_at <= now).count();
+
+        self.pending
+            .with_label_values(&["active"])
+            .set((pending.len() - expired) as i64);
+        self.pending
+            .with_label_values(&["expired"])
+            .set(expired as i64);
+        self.pending_bytes
+            .set(pending.iter().map(PendingDigidecs::size).sum::<usize>() as i64);
+    }
+
+    pub fn encode(&self) -> prometheus::Result<String> {
+        TextEncoder::new().encode_to_string(&self.registry.gather())
+    }
+}
+
+/// Middleware counting the outcome of every request to a known route
+pub async fn count_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let res = next.call(req).await?;
+
+    let (Some(runtime), Some(route)) = (
+        res.request().app_data::<WRuntime>(),
+        res.request().match_pattern(),
+    ) else {
+        return Ok(res);
+    };
+
+    let outcome = match res.response().error() {
+        None => "ok".to_string(),
+        Some(e) => match e.as_error::<Error>() {
+            Some(e) => e.code().to_string(),
+            None => format!("http_{}", res.status().as_u16()),
+        },
+    };
+
+    runtime
+        .metrics
+        .requests
+        .with_label_values(&[&route, &outcome])
+        .inc();
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encodes_metrics() {
+        let metrics = Metrics::new().unwrap();
+        metrics
+            .requests
+            .with_label_values(&["/api/digidecs/start", "invalid_iban"])
+            .inc();
+        metrics.upload_size.observe(2048.0);
+
+        let encoded = metrics.encode().unwrap();
+        assert!(encoded.contains(
+            r#"digidecs_requests_total{outcome="invalid_iban",route="/api/digidecs/start"} 1"#
+        ));
+        assert!(encoded.contains("digidecs_upload_size_bytes_count 1"));
+    }
+}
@@ -1,41 +1,170 @@
 use crate::args::AppArgs;
-use crate::email::ipv4::get_local_v4;
+use crate::email::address::SourceAddrMonitor;
+use crate::email::dkim::load_signer;
+use crate::email::template::Templates;
 use crate::file::AppConfig;
+use crate::i18n::Catalog;
+use crate::oidc::OidcClient;
+use crate::server::bot::BotProtection;
+use crate::server::cors::CorsPolicy;
+use crate::server::metrics::Metrics;
+use crate::server::rate_limit::RateLimiter;
 use crate::server::types::{RuntimeData, WArgs, WConfig, WRuntime};
-use actix_cors::Cors;
+use crate::signing::Signer;
+use crate::storage::Storage;
 use actix_route_config::Routable;
+use actix_web::middleware::from_fn;
 use actix_web::{App, HttpServer};
 use noiseless_tracing_actix_web::NoiselessRootSpanBuilder;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{SocketAddr, TcpListener};
 use std::sync::Arc;
-use tracing::info;
+use std::time::Duration;
+use tracing::{info, warn};
 
+mod bot;
+mod cors;
+mod localize;
+mod metrics;
+mod rate_limit;
 mod routes;
+mod session;
 mod types;
+mod validation;
 
 pub async fn run_server(config: AppConfig, args: AppArgs) -> color_eyre::Result<()> {
-    let port = config.server.port;
+    let listeners = config
+        .server
+        .listen
+        .iter()
+        .map(|addr| listen(SocketAddr::new(*addr, config.server.port)))
+        .collect::<std::io::Result<Vec<_>>>()?;
+
+    let dkim = match &config.smtp.dkim {
+        Some(dkim) => {
+            info!(
+                "Signing outgoing email with DKIM for {} (selector {})",
+                dkim.domain, dkim.selector
+            );
+            Some(Arc::new(load_signer(dkim).await?))
+        }
+        None => None,
+    };
+
+    let signer = match &config.signing_key {
+        Some(key) => Signer::new(key.as_bytes()),
+        None => {
+            warn!(
+                "No signing key configured. Download links become invalid when the server restarts"
+            );
+            Signer::random()
+        }
+    };
+
+    if let Some(dir) = &config.templates_dir {
+        info!("Using email templates from {}", dir.display());
+    }
+    let catalog = Arc::new(Catalog::load(
+        config.locales_dir.as_deref(),
+        config.default_locale.clone(),
+    )?);
+    info!(
+        "Loaded message catalogs for {}",
+        catalog
+            .locales()
+            .map(|locale| locale.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    let templates = Arc::new(Templates::load(
+        config.templates_dir.as_deref(),
+        catalog.clone(),
+    )?);
+    templates.clone().watch();
+
+    let source_addrs =
+        Arc::new(SourceAddrMonitor::new(config.smtp.source_address, config.smtp.probe()).await);
+    source_addrs
+        .clone()
+        .watch(Duration::from_secs(config.smtp.probe_interval_secs));
+
+    let oidc = match &config.oidc {
+        Some(oidc) => {
+            info!("Members can log in with {}", oidc.issuer);
+            let redirect_uri = format!("{}/api/auth/callback", config.server.public_url());
+            Some(Arc::new(OidcClient::new(oidc.clone(), redirect_uri)?))
+        }
+        None => None,
+    };
 
     let runtime_data = RuntimeData {
-        local_v4_addr: get_local_v4().await?,
+        source_addrs: source_addrs.clone(),
+        metrics: Arc::new(Metrics::new()?),
+        rate_limiter: Arc::new(RateLimiter::new(config.rate_limit.clone())),
+        bot_protection: Arc::new(BotProtection::new(
+            config.bot_protection.clone(),
+            signer.clone(),
+        )),
+        dkim,
+        oidc,
+        storage: Arc::new(Storage::open(&config.storage.data_dir).await?),
+        signer,
+        templates,
+        catalog,
         pending_digidecs: Arc::new(tokio::sync::Mutex::new(vec![])),
+        smtp_check: Arc::new(tokio::sync::Mutex::new(None)),
     };
 
-    info!("Using {} for SMTP connections", runtime_data.local_v4_addr);
+    info!(
+        "Using {} for SMTP connections",
+        runtime_data.source_addrs.current()
+    );
+
+    let cors = CorsPolicy::new(&config.server, args.permissive_cors)?;
+    match &cors {
+        CorsPolicy::Permissive => {
+            warn!("Allowing requests from any origin, do not use this in production")
+        }
+        CorsPolicy::Restricted { origins, .. } => {
+            info!("Allowing requests from {}", origins.join(", "))
+        }
+    }
 
     let host = config.server.domain.clone();
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .wrap(Cors::permissive())
+            .wrap(cors.build())
+            .wrap(from_fn(localize::localize_errors))
+            .wrap(from_fn(metrics::count_requests))
             .wrap(tracing_actix_web::TracingLogger::<NoiselessRootSpanBuilder>::new())
             .app_data(WConfig::new(config.clone()))
             .app_data(WArgs::new(args.clone()))
             .app_data(WRuntime::new(runtime_data.clone()))
             .configure(routes::Router::configure)
     })
-    .bind(format!("0.0.0.0:{port}"))?
-    .server_hostname(&host)
-    .run()
-    .await?;
+    .server_hostname(&host);
+
+    for listener in listeners {
+        info!("Listening on {}", listener.local_addr()?);
+        server = server.listen(listener)?;
+    }
+
+    server.run().await?;
 
     Ok(())
 }
+
+/// Listen on `addr`. IPv6 sockets only accept IPv6 connections,
+/// so that an IPv4 and an IPv6 address can be listened on with the same port.
+fn listen(addr: SocketAddr) -> std::io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    Ok(socket.into())
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::HttpRequest;
+
+use crate::file::{RateLimitConfig, TokenBucketConfig};
+use crate::server::types::{Error, WResult};
+
+/// Buckets are only removed once full again, and at most this often
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Limits how often declarations can be submitted, per client IP address and per submitter email address
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    per_ip: Limiter<IpAddr>,
+    per_email: Limiter<String>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            per_ip: Limiter::new(config.per_ip),
+            per_email: Limiter::new(config.per_email),
+            config,
+        }
+    }
+
+    /// Take a token from the bucket of the client of `req`
+    ///
+    /// # Errors
+    ///
+    /// [Error::RateLimited] if the bucket is empty
+    pub fn check_ip(&self, req: &HttpRequest) -> WResult<()> {
+        match self.client_ip(req) {
+            Some(ip) => self.per_ip.take(limit_key(ip), Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    /// Take a token from the bucket of the submitter's email address
+    ///
+    /// # Errors
+    ///
+    /// [Error::RateLimited] if the bucket is empty
+    pub fn check_email(&self, email: &str) -> WResult<()> {
+        self.per_email
+            .take(email.trim().to_lowercase(), Instant::now())
+    }
+
+    /// The address of the client. If the request was forwarded by a trusted proxy,
+    /// this is the last address in the configured header that is not a trusted proxy.
+    fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        let peer = req.peer_addr()?.ip();
+        let Some(header) = &self.config.client_ip_header else {
+            return Some(peer);
+        };
+        if !self.config.trusted_proxies.contains(&peer) {
+            return Some(peer);
+        }
+
+        let forwarded = req
+            .headers()
+            .get_all(header.as_str())
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+            .collect::<Vec<_>>();
+
+        forwarded
+            .iter()
+            .rev()
+            .find(|addr| !self.config.trusted_proxies.contains(addr))
+            .or(forwarded.first())
+            .copied()
+            .or(Some(peer))
+    }
+}
+
+/// IPv6 clients usually have a whole /64 to themselves, so they are limited per /64
+fn limit_key(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => ip,
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(Ipv6Addr::from(u128::from(v6) & !((1u128 << 64) - 1))),
+        },
+    }
+}
+
+struct Limiter<K> {
+    config: Option<TokenBucketConfig>,
+    state: Mutex<LimiterState<K>>,
+}
+
+struct LimiterState<K> {
+    buckets: HashMap<K, Bucket>,
+    last_pruned: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl<K: Eq + Hash> Limiter<K> {
+    fn new(config: Option<TokenBucketConfig>) -> Self {
+        Self {
+            config,
+            state: Mutex::new(LimiterState {
+                buckets: HashMap::new(),
+                last_pruned: Instant::now(),
+            }),
+        }
+    }
+
+    fn take(&self, key: K, now: Instant) -> WResult<()> {
+        let Some(config) = self.config else {
+            return Ok(());
+        };
+        // Tokens per second
+        let rate = f64::from(config.per_hour) / 3600.0;
+        let burst = f64::from(config.burst);
+        let refill = |bucket: &Bucket| {
+            (bucket.tokens + now.saturating_duration_since(bucket.updated).as_secs_f64() * rate)
+                .min(burst)
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if now.saturating_duration_since(state.last_pruned) >= PRUNE_INTERVAL {
+            state.buckets.retain(|_, bucket| refill(bucket) < burst);
+            state.last_pruned = now;
+        }
+
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else if rate > 0.0 {
+            Err(Error::RateLimited(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / rate,
+            )))
+        } else {
+            Err(Error::RateLimited(Duration::from_secs(3600)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn token_bucket() {
+        let limiter = Limiter::new(Some(TokenBucketConfig {
+            burst: 2,
+            per_hour: 60,
+        }));
+        let now = Instant::now();
+
+        assert!(limiter.take("a", now).is_ok());
+        assert!(limiter.take("a", now).is_ok());
+        match limiter.take("a", now) {
+            Err(Error::RateLimited(retry_after)) => {
+                assert_eq!(retry_after.as_secs(), 60)
+            }
+            other => panic!("Expected to be rate limited, got {other:?}"),
+        }
+        // Other keys have their own bucket
+        assert!(limiter.take("b", now).is_ok());
+
+        // One token per minute
+        assert!(limiter.take("a", now + Duration::from_secs(60)).is_ok());
+        assert!(limiter.take("a", now + Duration::from_secs(60)).is_err());
+    }
+
+    #[test]
+    fn client_ip_from_trusted_proxy() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            client_ip_header: Some("X-Forwarded-For".to_string()),
+            trusted_proxies: vec!["127.0.0.1".parse().unwrap(), "10.0.0.1".parse().unwrap()],
+            ..RateLimitConfig::default()
+        });
+        let request = |peer: &str, forwarded: &str| {
+            TestRequest::default()
+                .peer_addr(format!("{peer}:1234").parse().unwrap())
+                .insert_header(("X-Forwarded-For", forwarded))
+                .to_http_request()
+        };
+
+        assert_eq!(
+            limiter.client_ip(&request("127.0.0.1", "192.0.2.1, 198.51.100.7, 10.0.0.1")),
+            Some("198.51.100.7".parse().unwrap())
+        );
+        // The header can be set by anyone, it is only trusted from proxies
+        assert_eq!(
+            limiter.client_ip(&request("203.0.113.5", "192.0.2.1")),
+            Some("203.0.113.5".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn ipv6_limited_per_prefix() {
+        assert_eq!(
+            limit_key("2001:db8:1:2:3:4:5:6".parse().unwrap()),
+            "2001:db8:1:2::".parse::<IpAddr>().unwrap()
+        );
+        assert_eq!(
+            limit_key("::ffff:192.0.2.1".parse().unwrap()),
+            "192.0.2.1".parse::<IpAddr>().unwrap()
+        );
+    }
+}
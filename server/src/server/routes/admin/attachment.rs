@@ -0,0 +1,64 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::{instrument, trace};
+
+use crate::server::routes::admin::{authorize, parse_reference};
+use crate::server::types::{
+    unknown_attachment, unknown_declaration, Error, WConfig, WResult, WRuntime,
+};
+
+/// Download an attachment of a stored declaration
+#[utoipa::path(
+    get,
+    path = "/api/admin/declarations/{reference}/attachments/{index}",
+    params(
+        ("reference" = String, Path, example = "DD-2026-0042"),
+        ("index" = usize, Path, description = "Index of the attachment in the declaration"),
+    ),
+    responses(
+        (
+            status = 200,
+            description = "The attachment",
+            content_type = "application/octet-stream",
+            body = Vec<u8>
+        ),
+        Error,
+    ),
+    security(("api_token" = []), ("session" = [])),
+)]
+#[instrument(skip_all)]
+pub async fn attachment(
+    req: HttpRequest,
+    path: web::Path<(String, usize)>,
+    config: WConfig,
+    runtime: WRuntime,
+) -> WResult<HttpResponse> {
+    let by = authorize(&req, &config, &runtime).await?;
+    let (reference, index) = path.into_inner();
+    let reference = parse_reference(&reference)?;
+
+    let declaration = runtime
+        .storage
+        .load_declaration(&reference)
+        .await
+        .map_err(unknown_declaration)?;
+    let attachment = declaration
+        .attachments
+        .get(index)
+        .ok_or(Error::UnknownAttachment)?;
+    let content = runtime
+        .storage
+        .read_attachment(&reference, index)
+        .await
+        .map_err(unknown_attachment)?;
+
+    trace!("Serving attachment {index} of {reference} to {by}");
+
+    Ok(HttpResponse::Ok()
+        .content_type(attachment.mime.as_str())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(attachment.name.clone())],
+        })
+        .body(content))
+}
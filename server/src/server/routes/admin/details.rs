@@ -0,0 +1,102 @@
+use actix_web::{web, HttpRequest};
+use serde::Serialize;
+use time::OffsetDateTime;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::file::AppConfig;
+use crate::server::routes::admin::{authorize, parse_reference};
+use crate::server::types::{unknown_declaration, Error, WConfig, WResult, WRuntime};
+use crate::storage::{Declaration, DeclarationStatus, Reference, StatusChange};
+
+#[derive(Serialize, ToSchema)]
+pub struct DeclarationDetails {
+    #[schema(value_type = String, example = "DD-2026-0042")]
+    reference: Reference,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    submitted_at: OffsetDateTime,
+    name: String,
+    iban: String,
+    email: String,
+    address: String,
+    value: f64,
+    what: String,
+    commission: String,
+    notes: Option<String>,
+    attachments: Vec<AttachmentDetails>,
+    /// Who the declaration was sent to
+    recipients: Vec<String>,
+    status: DeclarationStatus,
+    /// Changes of the status, oldest first
+    history: Vec<StatusChange>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AttachmentDetails {
+    name: String,
+    mime: String,
+    /// Where to download the attachment, with the same credentials
+    url: String,
+}
+
+impl DeclarationDetails {
+    pub fn new(config: &AppConfig, declaration: Declaration) -> Self {
+        let reference = declaration.reference;
+
+        Self {
+            reference,
+            submitted_at: declaration.submitted_at,
+            name: declaration.name,
+            iban: declaration.iban,
+            email: declaration.email,
+            address: declaration.address,
+            value: declaration.value,
+            what: declaration.what,
+            commission: declaration.commission,
+            notes: declaration.notes,
+            attachments: declaration
+                .attachments
+                .into_iter()
+                .enumerate()
+                .map(|(index, att)| AttachmentDetails {
+                    name: att.name,
+                    mime: att.mime,
+                    url: format!(
+                        "{}/api/admin/declarations/{reference}/attachments/{index}",
+                        config.server.public_url()
+                    ),
+                })
+                .collect(),
+            recipients: declaration.recipients.to,
+            status: declaration.status,
+            history: declaration.history,
+        }
+    }
+}
+
+/// A stored declaration
+#[utoipa::path(
+    get,
+    path = "/api/admin/declarations/{reference}",
+    params(("reference" = String, Path, example = "DD-2026-0042")),
+    responses((status = 200, description = "The declaration", body = DeclarationDetails), Error),
+    security(("api_token" = []), ("session" = [])),
+)]
+#[instrument(skip_all)]
+pub async fn details(
+    req: HttpRequest,
+    reference: web::Path<String>,
+    config: WConfig,
+    runtime: WRuntime,
+) -> WResult<web::Json<DeclarationDetails>> {
+    authorize(&req, &config, &runtime).await?;
+
+    let declaration = runtime
+        .storage
+        .load_declaration(&parse_reference(&reference)?)
+        .await
+        .map_err(unknown_declaration)?;
+
+    Ok(web::Json(DeclarationDetails::new(&config, declaration)))
+}
@@ -0,0 +1,229 @@
+use actix_web::{web, HttpRequest};
+use serde::{Deserialize, Serialize};
+use time::format_description::well_known::Iso8601;
+use time::{Date, OffsetDateTime};
+use tracing::instrument;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::server::routes::admin::authorize;
+use crate::server::types::{Error, WConfig, WResult, WRuntime};
+use crate::storage::{Declaration, DeclarationStatus, Reference};
+
+/// The number of declarations returned if no limit is provided
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Query {
+    status: Option<DeclarationStatus>,
+    /// Only declarations for this commission, ignoring case
+    commission: Option<String>,
+    /// Only declarations whose reference, name, email address or description contain this, ignoring case
+    search: Option<String>,
+    /// Only declarations submitted on or after this date
+    #[param(example = "2026-01-01")]
+    from: Option<String>,
+    /// Only declarations submitted on or before this date
+    #[param(example = "2026-12-31")]
+    to: Option<String>,
+    /// The number of matching declarations to skip
+    #[serde(default)]
+    offset: usize,
+    /// The maximum number of declarations to return, 100 by default
+    limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeclarationList {
+    /// The number of matching declarations, including those not returned because of the limit
+    total: usize,
+    /// The matching declarations, newest first
+    declarations: Vec<DeclarationSummary>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeclarationSummary {
+    #[schema(value_type = String, example = "DD-2026-0042")]
+    reference: Reference,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    submitted_at: OffsetDateTime,
+    name: String,
+    email: String,
+    value: f64,
+    what: String,
+    commission: String,
+    status: DeclarationStatus,
+    attachment_count: usize,
+}
+
+impl From<Declaration> for DeclarationSummary {
+    fn from(declaration: Declaration) -> Self {
+        Self {
+            reference: declaration.reference,
+            submitted_at: declaration.submitted_at,
+            name: declaration.name,
+            email: declaration.email,
+            value: declaration.value,
+            what: declaration.what,
+            commission: declaration.commission,
+            status: declaration.status,
+            attachment_count: declaration.attachments.len(),
+        }
+    }
+}
+
+/// The filters of a [Query], parsed
+struct Filter {
+    status: Option<DeclarationStatus>,
+    commission: Option<String>,
+    search: Option<String>,
+    from: Option<Date>,
+    to: Option<Date>,
+}
+
+impl Filter {
+    fn new(query: &Query) -> WResult<Self> {
+        let date = |value: &Option<String>, field| {
+            value
+                .as_deref()
+                .map(|value| Date::parse(value, &Iso8601::DEFAULT))
+                .transpose()
+                .map_err(|_| Error::InvalidField(field))
+        };
+
+        Ok(Self {
+            status: query.status,
+            commission: query.commission.as_ref().map(|c| c.to_lowercase()),
+            search: query.search.as_ref().map(|s| s.to_lowercase()),
+            from: date(&query.from, "from")?,
+            to: date(&query.to, "to")?,
+        })
+    }
+
+    fn matches(&self, declaration: &Declaration) -> bool {
+        let submitted = declaration.submitted_at.date();
+
+        self.status
+            .is_none_or(|status| declaration.status == status)
+            && self
+                .commission
+                .as_ref()
+                .is_none_or(|commission| declaration.commission.to_lowercase() == *commission)
+            && self.search.as_ref().is_none_or(|search| {
+                [
+                    &declaration.reference.to_string(),
+                    &declaration.name,
+                    &declaration.email,
+                    &declaration.what,
+                ]
+                .iter()
+                .any(|value| value.to_lowercase().contains(search))
+            })
+            && self.from.is_none_or(|from| submitted >= from)
+            && self.to.is_none_or(|to| submitted <= to)
+    }
+}
+
+/// List the stored declarations, optionally filtered
+#[utoipa::path(
+    get,
+    path = "/api/admin/declarations",
+    params(Query),
+    responses((status = 200, description = "The matching declarations", body = DeclarationList), Error),
+    security(("api_token" = []), ("session" = [])),
+)]
+#[instrument(skip_all)]
+pub async fn list(
+    req: HttpRequest,
+    query: web::Query<Query>,
+    config: WConfig,
+    runtime: WRuntime,
+) -> WResult<web::Json<DeclarationList>> {
+    authorize(&req, &config, &runtime).await?;
+    let filter = Filter::new(&query)?;
+
+    let matching = runtime
+        .storage
+        .list_declarations()
+        .await?
+        .into_iter()
+        .filter(|declaration| filter.matches(declaration))
+        .collect::<Vec<_>>();
+
+    Ok(web::Json(DeclarationList {
+        total: matching.len(),
+        declarations: matching
+            .into_iter()
+            .skip(query.offset)
+            .take(query.limit.unwrap_or(DEFAULT_LIMIT))
+            .map(DeclarationSummary::from)
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::routing::Recipients;
+
+    fn declaration() -> Declaration {
+        Declaration {
+            reference: "DD-2026-0042".parse().unwrap(),
+            submitted_at: OffsetDateTime::from_unix_timestamp(1_780_000_000).unwrap(),
+            name: "Jan Jansen".to_string(),
+            iban: "NL91ABNA0417164300".to_string(),
+            email: "jan@example.com".to_string(),
+            address: "Straat 1".to_string(),
+            value: 12.5,
+            what: "Pizza".to_string(),
+            commission: "Bestuur".to_string(),
+            notes: None,
+            attachments: vec![],
+            recipients: Recipients {
+                rule: None,
+                to: vec![],
+                cc: vec![],
+                bcc: vec![],
+            },
+            status: DeclarationStatus::Submitted,
+            history: vec![],
+        }
+    }
+
+    fn filter(query: &str) -> Filter {
+        Filter::new(&web::Query::<Query>::from_query(query).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn filters() {
+        let declaration = declaration();
+        assert_eq!(declaration.submitted_at.date().to_string(), "2026-05-28");
+
+        for query in [
+            "",
+            "status=submitted",
+            "commission=bestuur",
+            "search=PIZZA",
+            "search=0042",
+            "from=2026-05-28&to=2026-05-28",
+        ] {
+            assert!(filter(query).matches(&declaration), "{query}");
+        }
+
+        for query in [
+            "status=paid",
+            "commission=other",
+            "search=pasta",
+            "from=2026-05-29",
+            "to=2026-05-27",
+        ] {
+            assert!(!filter(query).matches(&declaration), "{query}");
+        }
+
+        assert!(matches!(
+            Filter::new(&web::Query::<Query>::from_query("from=tomorrow").unwrap()),
+            Err(Error::InvalidField("from"))
+        ));
+    }
+}
@@ -0,0 +1,80 @@
+use actix_route_config::Routable;
+use actix_web::http::header::AUTHORIZATION;
+use actix_web::web::ServiceConfig;
+use actix_web::{web, HttpRequest};
+
+use crate::file::AppConfig;
+use crate::server::session::Session;
+use crate::server::types::{Error, RuntimeData, WResult};
+use crate::storage::Reference;
+
+pub mod attachment;
+pub mod details;
+pub mod list;
+pub mod status;
+
+pub struct Router;
+
+impl Routable for Router {
+    fn configure(config: &mut ServiceConfig) {
+        config.service(
+            web::scope("/admin")
+                .route("/declarations", web::get().to(list::list))
+                .route("/declarations/{reference}", web::get().to(details::details))
+                .route(
+                    "/declarations/{reference}/attachments/{index}",
+                    web::get().to(attachment::attachment),
+                )
+                .route(
+                    "/declarations/{reference}/status",
+                    web::post().to(status::status),
+                ),
+        );
+    }
+}
+
+/// Check that the request is made by the treasurer, with an API token
+/// in the `Authorization: Bearer` header or a login with the treasurer role.
+/// Returns who made the request, for the history of declarations.
+///
+/// # Errors
+///
+/// [Error::AdminUnauthenticated] if neither is present or the token is invalid,
+/// [Error::Forbidden] if the logged in member does not have the treasurer role
+pub async fn authorize(
+    req: &HttpRequest,
+    config: &AppConfig,
+    runtime: &RuntimeData,
+) -> WResult<String> {
+    if let Some(header) = req.headers().get(AUTHORIZATION) {
+        let token = header
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(Error::AdminUnauthenticated)?;
+
+        let tokens = runtime.storage.load_api_tokens().await?;
+        let token = tokens
+            .find(token.trim())
+            .ok_or(Error::AdminUnauthenticated)?;
+        return Ok(format!("token {}", token.name));
+    }
+
+    let role = config
+        .oidc
+        .as_ref()
+        .and_then(|oidc| oidc.treasurer_role.as_ref())
+        .ok_or(Error::AdminUnauthenticated)?;
+    let session = Session::from_request(req, &runtime.signer).ok_or(Error::AdminUnauthenticated)?;
+
+    if !session.identity.roles.contains(role) {
+        return Err(Error::Forbidden);
+    }
+
+    Ok(session.identity.email)
+}
+
+/// Parse the reference in the path. Invalid references are reported as unknown.
+fn parse_reference(reference: &str) -> WResult<Reference> {
+    reference.parse().map_err(|_| Error::UnknownDeclaration)
+}
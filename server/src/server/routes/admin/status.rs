@@ -0,0 +1,61 @@
+use actix_web::{web, HttpRequest};
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tracing::{info, instrument};
+use utoipa::ToSchema;
+
+use crate::server::routes::admin::details::DeclarationDetails;
+use crate::server::routes::admin::{authorize, parse_reference};
+use crate::server::types::{unknown_declaration, Error, WConfig, WResult, WRuntime};
+use crate::storage::{DeclarationStatus, StatusChange};
+
+#[derive(Deserialize, ToSchema)]
+pub struct StatusRequest {
+    status: DeclarationStatus,
+    /// Why the status was changed, e.g. why the declaration was rejected
+    #[serde(default)]
+    note: Option<String>,
+}
+
+/// Change the status of a stored declaration. The change is recorded in its history.
+#[utoipa::path(
+    post,
+    path = "/api/admin/declarations/{reference}/status",
+    params(("reference" = String, Path, example = "DD-2026-0042")),
+    request_body = StatusRequest,
+    responses((status = 200, description = "The updated declaration", body = DeclarationDetails), Error),
+    security(("api_token" = []), ("session" = [])),
+)]
+#[instrument(skip_all)]
+pub async fn status(
+    req: HttpRequest,
+    reference: web::Path<String>,
+    payload: web::Json<StatusRequest>,
+    config: WConfig,
+    runtime: WRuntime,
+) -> WResult<web::Json<DeclarationDetails>> {
+    let by = authorize(&req, &config, &runtime).await?;
+    let reference = parse_reference(&reference)?;
+    let payload = payload.into_inner();
+
+    let declaration = runtime
+        .storage
+        .update_declaration(&reference, |declaration| {
+            declaration.status = payload.status;
+            declaration.history.push(StatusChange {
+                status: payload.status,
+                at: OffsetDateTime::now_utc(),
+                by: by.clone(),
+                note: payload.note.filter(|note| !note.trim().is_empty()),
+            });
+        })
+        .await
+        .map_err(unknown_declaration)?;
+
+    info!(
+        "{by} changed the status of {reference} to {:?}",
+        payload.status
+    );
+
+    Ok(web::Json(DeclarationDetails::new(&config, declaration)))
+}
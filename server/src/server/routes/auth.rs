@@ -0,0 +1,207 @@
+use actix_route_config::Routable;
+use actix_web::http::header::LOCATION;
+use actix_web::web::ServiceConfig;
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tracing::{info, instrument};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::oidc::{LoginRequest, OidcError};
+use crate::server::session::{
+    cookie, decode_signed, encode_signed, Session, LOGIN_COOKIE, SESSION_COOKIE,
+};
+use crate::server::types::{Error, WConfig, WResult, WRuntime};
+
+/// How long the member can take to log in at the issuer
+const LOGIN_VALIDITY: Duration = Duration::minutes(10);
+
+pub struct Router;
+
+impl Routable for Router {
+    fn configure(config: &mut ServiceConfig) {
+        config.service(
+            web::scope("/auth")
+                .route("/login", web::get().to(login))
+                .route("/callback", web::get().to(callback))
+                .route("/session", web::get().to(session))
+                .route("/logout", web::post().to(logout)),
+        );
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LoginQuery {
+    /// Path to return to after logging in
+    return_to: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionResponse {
+    /// Whether members can log in
+    enabled: bool,
+    /// Whether members must log in before submitting a declaration
+    required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<User>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct User {
+    name: String,
+    email: String,
+    address: Option<String>,
+}
+
+/// Log in at the issuer. Redirects back to the form afterwards.
+#[utoipa::path(
+    get,
+    path = "/api/auth/login",
+    params(LoginQuery),
+    responses((status = 302, description = "Redirect to the issuer"), Error),
+)]
+#[instrument(skip_all)]
+pub async fn login(
+    query: web::Query<LoginQuery>,
+    config: WConfig,
+    runtime: WRuntime,
+) -> WResult<HttpResponse> {
+    let oidc = runtime.oidc.as_ref().ok_or(Error::LoginUnavailable)?;
+
+    // Only paths on this server, so the login cannot be used to redirect elsewhere
+    let return_to = query
+        .into_inner()
+        .return_to
+        .filter(|path| path.starts_with('/') && !path.starts_with("//") && !path.contains('\\'))
+        .unwrap_or_else(|| "/".to_string());
+
+    let (url, login) = oidc.authorization_url(return_to).await?;
+    let login = encode_signed(&runtime.signer, LOGIN_COOKIE, &login);
+
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .cookie(cookie(
+            &config,
+            LOGIN_COOKIE,
+            login,
+            "/api/auth",
+            LOGIN_VALIDITY,
+        ))
+        .finish())
+}
+
+/// The issuer redirects here after logging in
+#[utoipa::path(
+    get,
+    path = "/api/auth/callback",
+    params(CallbackQuery),
+    responses((status = 302, description = "Logged in, redirect to the form"), Error),
+)]
+#[instrument(skip_all)]
+pub async fn callback(
+    req: HttpRequest,
+    query: web::Query<CallbackQuery>,
+    config: WConfig,
+    runtime: WRuntime,
+) -> WResult<HttpResponse> {
+    let oidc = runtime.oidc.as_ref().ok_or(Error::LoginUnavailable)?;
+    let oidc_config = config.oidc.as_ref().ok_or(Error::LoginUnavailable)?;
+
+    let login = req
+        .cookie(LOGIN_COOKIE)
+        .and_then(|cookie| {
+            decode_signed::<LoginRequest>(&runtime.signer, LOGIN_COOKIE, cookie.value())
+        })
+        .filter(|login| query.state.as_ref() == Some(&login.state))
+        .ok_or(Error::InvalidLoginState)?;
+
+    if let Some(error) = &query.error {
+        let description = query.error_description.as_deref().unwrap_or_default();
+        return Err(OidcError::Denied(format!("{error} {description}").trim().to_string()).into());
+    }
+    let code = query.code.as_deref().ok_or(Error::InvalidLoginState)?;
+
+    let identity = oidc.exchange(&login, code).await?;
+    info!("{} logged in", identity.email);
+
+    let validity = Duration::hours(oidc_config.session_hours);
+    let session = Session {
+        identity,
+        expires: (OffsetDateTime::now_utc() + validity).unix_timestamp(),
+    };
+
+    Ok(HttpResponse::Found()
+        .insert_header((
+            LOCATION,
+            format!("{}{}", config.server.public_url(), login.return_to),
+        ))
+        .cookie(cookie(
+            &config,
+            SESSION_COOKIE,
+            encode_signed(&runtime.signer, SESSION_COOKIE, &session),
+            "/",
+            validity,
+        ))
+        .cookie(cookie(
+            &config,
+            LOGIN_COOKIE,
+            String::new(),
+            "/api/auth",
+            Duration::ZERO,
+        ))
+        .finish())
+}
+
+/// Whether login is enabled, and who is logged in
+#[utoipa::path(
+    get,
+    path = "/api/auth/session",
+    responses((status = 200, description = "The session", body = SessionResponse)),
+)]
+#[instrument(skip_all)]
+pub async fn session(
+    req: HttpRequest,
+    config: WConfig,
+    runtime: WRuntime,
+) -> web::Json<SessionResponse> {
+    let user = Session::from_request(&req, &runtime.signer).map(|session| User {
+        name: session.identity.name,
+        email: session.identity.email,
+        address: session.identity.address,
+    });
+
+    web::Json(SessionResponse {
+        enabled: config.oidc.is_some(),
+        required: config.oidc.as_ref().is_some_and(|oidc| oidc.required),
+        user: user.filter(|_| config.oidc.is_some()),
+    })
+}
+
+/// Log out. Does not log out at the issuer.
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    responses((status = 204, description = "Logged out")),
+)]
+#[instrument(skip_all)]
+pub async fn logout(config: WConfig) -> HttpResponse {
+    HttpResponse::NoContent()
+        .cookie(cookie(
+            &config,
+            SESSION_COOKIE,
+            String::new(),
+            "/",
+            Duration::ZERO,
+        ))
+        .finish()
+}
@@ -1,22 +1,110 @@
-use actix_web::web;
-use serde::Deserialize;
+use actix_web::http::header::{ContentRange, ContentRangeSpec, Header, CONTENT_RANGE};
+use actix_web::{web, HttpRequest};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tracing::{instrument, trace};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::server::types::{Empty, Error, WResult, WRuntime};
+use crate::file::UploadConfig;
+use crate::server::types::{
+    Error, PartialUpload, PendingDigidecsAttachment, WConfig, WResult, WRuntime,
+};
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Query {
     tracking_id: String,
     attachment_tracking_id: String,
+    /// Hex encoded SHA-256 of the complete attachment.
+    /// Checked once all bytes have been received.
+    sha256: Option<String>,
 }
 
+#[derive(Debug, PartialEq, Eq, Serialize, ToSchema)]
+pub struct UploadStatus {
+    /// The number of bytes received so far. A resumed upload continues at this offset.
+    received: u64,
+    /// The size of the attachment, if known
+    total: Option<u64>,
+    complete: bool,
+}
+
+impl From<&PendingDigidecsAttachment> for UploadStatus {
+    fn from(attachment: &PendingDigidecsAttachment) -> Self {
+        match (&attachment.content, &attachment.partial) {
+            (Some(content), _) => Self {
+                received: content.len() as u64,
+                total: Some(content.len() as u64),
+                complete: true,
+            },
+            (None, Some(partial)) => Self {
+                received: partial.data.len() as u64,
+                total: Some(partial.total),
+                complete: false,
+            },
+            (None, None) => Self {
+                received: 0,
+                total: None,
+                complete: false,
+            },
+        }
+    }
+}
+
+/// A chunk of an attachment, as described by a `Content-Range` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Chunk {
+    start: u64,
+    /// Inclusive
+    end: u64,
+    total: u64,
+}
+
+/// Upload the content of an attachment of a started digidecs.
+///
+/// The attachment can be uploaded in one request, or in chunks with a `Content-Range` header,
+/// e.g. `bytes 0-1048575/10485760`. Chunks must be sent in order.
+/// If an upload is interrupted, query the received offset and continue from there.
+/// The limits on the size of a file and on the total size of the files of a digidecs
+/// apply to the size of the complete attachment.
+#[utoipa::path(
+    post,
+    path = "/api/digidecs/attachment",
+    params(
+        Query,
+        (
+            "Content-Range" = Option<String>,
+            Header,
+            description = "The bytes contained in this chunk, e.g. `bytes 0-1048575/10485760`"
+        ),
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "The attachment, or chunk, was received", body = UploadStatus),
+        Error,
+    ),
+)]
 #[instrument(skip_all)]
 pub async fn attachment(
+    req: HttpRequest,
     query: web::Query<Query>,
     payload: web::Payload,
+    config: WConfig,
     runtime: WRuntime,
-) -> WResult<Empty> {
-    let payload = payload.to_bytes_limited(usize::MAX).await??;
+) -> WResult<web::Json<UploadStatus>> {
+    let chunk = if req.headers().contains_key(CONTENT_RANGE) {
+        Some(parse_content_range(&req)?)
+    } else {
+        None
+    };
+
+    // Reject a too large attachment before reading any of it
+    if chunk.is_some_and(|chunk| chunk.total > config.uploads.max_file_size) {
+        return Err(Error::BodyTooLarge);
+    }
+
+    let limit = usize::try_from(config.uploads.max_file_size).unwrap_or(usize::MAX);
+    let payload = payload.to_bytes_limited(limit).await??;
     let payload = payload.to_vec();
 
     trace!("Received new attachment ({} B)", payload.len());
@@ -28,13 +116,252 @@ pub async fn attachment(
         .find(|digidecs| digidecs.tracking_id.eq(&query.tracking_id))
         .ok_or(Error::UnknownTrackingId)?;
 
+    let stored = digidecs
+        .attachments
+        .iter()
+        .filter(|att| att.tracking_id != query.attachment_tracking_id)
+        .map(reserved_size)
+        .sum::<u64>();
+    let size = chunk.map_or(payload.len() as u64, |chunk| chunk.total);
+    check_total_size(&config.uploads, stored, size)?;
+
     let attachment = digidecs
         .attachments
         .iter_mut()
         .find(|att| att.tracking_id.eq(&query.attachment_tracking_id))
         .ok_or(Error::UnknownAttachmentTrackingId)?;
 
-    attachment.content = Some(payload);
+    let status = receive(attachment, chunk, payload, query.sha256.as_deref())?;
+    // Once per attachment, as for a submission, not once per chunk
+    if let (true, Some(total)) = (status.complete, status.total) {
+        runtime.metrics.upload_size.observe(total as f64);
+    }
+
+    Ok(web::Json(status))
+}
+
+/// Query how much of an attachment has been received, to resume an interrupted upload
+#[utoipa::path(
+    get,
+    path = "/api/digidecs/attachment",
+    params(Query),
+    responses((status = 200, description = "The upload status", body = UploadStatus), Error),
+)]
+#[instrument(skip_all)]
+pub async fn status(
+    query: web::Query<Query>,
+    runtime: WRuntime,
+) -> WResult<web::Json<UploadStatus>> {
+    let lock = runtime.pending_digidecs.lock().await;
+
+    let digidecs = lock
+        .iter()
+        .find(|digidecs| digidecs.tracking_id.eq(&query.tracking_id))
+        .ok_or(Error::UnknownTrackingId)?;
+
+    let attachment = digidecs
+        .attachments
+        .iter()
+        .find(|att| att.tracking_id.eq(&query.attachment_tracking_id))
+        .ok_or(Error::UnknownAttachmentTrackingId)?;
+
+    Ok(web::Json(UploadStatus::from(attachment)))
+}
+
+fn parse_content_range(req: &HttpRequest) -> WResult<Chunk> {
+    match ContentRange::parse(req)
+        .map_err(|_| Error::InvalidContentRange)?
+        .0
+    {
+        ContentRangeSpec::Bytes {
+            range: Some((start, end)),
+            instance_length: Some(total),
+        } if start <= end && end < total => Ok(Chunk { start, end, total }),
+        _ => Err(Error::InvalidContentRange),
+    }
+}
+
+/// Store an uploaded attachment, or a chunk of it.
+/// Once the attachment is complete its checksum is verified, if provided.
+fn receive(
+    attachment: &mut PendingDigidecsAttachment,
+    chunk: Option<Chunk>,
+    payload: Vec<u8>,
+    sha256: Option<&str>,
+) -> WResult<UploadStatus> {
+    let Some(chunk) = chunk else {
+        verify_checksum(&payload, sha256)?;
+        attachment.partial = None;
+        attachment.content = Some(payload);
+        return Ok(UploadStatus::from(&*attachment));
+    };
+
+    if chunk.end - chunk.start + 1 != payload.len() as u64 {
+        return Err(Error::InvalidContentRange);
+    }
+
+    // Starting at zero (re)starts the upload
+    if chunk.start == 0 {
+        attachment.content = None;
+        attachment.partial = Some(PartialUpload {
+            data: Vec::with_capacity(payload.len()),
+            total: chunk.total,
+        });
+    }
+
+    let partial = match &mut attachment.partial {
+        Some(partial) if partial.data.len() as u64 == chunk.start => partial,
+        _ => {
+            return Err(Error::UploadOffsetMismatch(
+                UploadStatus::from(&*attachment).received,
+            ))
+        }
+    };
+    if partial.total != chunk.total {
+        return Err(Error::InvalidContentRange);
+    }
+
+    partial.data.extend_from_slice(&payload);
+
+    if partial.data.len() as u64 == partial.total {
+        let data = attachment.partial.take().unwrap().data;
+        // On a mismatch the client has to upload the attachment from the start again
+        verify_checksum(&data, sha256)?;
+        attachment.content = Some(data);
+    }
+
+    Ok(UploadStatus::from(&*attachment))
+}
+
+/// The bytes an attachment takes up, including those of a partial upload still to be received
+fn reserved_size(attachment: &PendingDigidecsAttachment) -> u64 {
+    match (&attachment.content, &attachment.partial) {
+        (Some(content), _) => content.len() as u64,
+        (None, Some(partial)) => partial.total,
+        (None, None) => 0,
+    }
+}
+
+/// Check that an attachment of `size` bytes fits next to the `stored` bytes
+/// of the other attachments of the digidecs
+fn check_total_size(limits: &UploadConfig, stored: u64, size: u64) -> WResult<()> {
+    if stored.saturating_add(size) > limits.max_total_size {
+        Err(Error::BodyTooLarge)
+    } else {
+        Ok(())
+    }
+}
+
+fn verify_checksum(data: &[u8], sha256: Option<&str>) -> WResult<()> {
+    match sha256 {
+        Some(expected)
+            if !expected.eq_ignore_ascii_case(&format!("{:x}", Sha256::digest(data))) =>
+        {
+            Err(Error::ChecksumMismatch)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn attachment() -> PendingDigidecsAttachment {
+        PendingDigidecsAttachment {
+            name: "bon.pdf".to_string(),
+            tracking_id: "abc".to_string(),
+            mime: "application/pdf".to_string(),
+            content: None,
+            partial: None,
+        }
+    }
+
+    fn chunk(start: u64, end: u64, total: u64) -> Option<Chunk> {
+        Some(Chunk { start, end, total })
+    }
+
+    #[test]
+    fn resumes_at_offset() {
+        let data = b"hello world".to_vec();
+        let sha256 = format!("{:x}", Sha256::digest(&data));
+        let mut att = attachment();
+
+        let status = receive(&mut att, chunk(0, 4, 11), data[..5].to_vec(), None).unwrap();
+        assert_eq!(status.received, 5);
+        assert!(!status.complete);
+
+        // A chunk which was already received, or skips ahead, is rejected
+        assert!(matches!(
+            receive(&mut att, chunk(3, 7, 11), data[3..8].to_vec(), None),
+            Err(Error::UploadOffsetMismatch(5))
+        ));
+
+        let status = receive(
+            &mut att,
+            chunk(5, 10, 11),
+            data[5..].to_vec(),
+            Some(&sha256),
+        )
+        .unwrap();
+        assert!(status.complete);
+        assert_eq!(att.content, Some(data));
+    }
+
+    #[test]
+    fn checksum_mismatch_restarts() {
+        let mut att = attachment();
+        let wrong = format!("{:x}", Sha256::digest(b"other"));
+
+        receive(&mut att, chunk(0, 2, 6), b"abc".to_vec(), None).unwrap();
+        assert!(matches!(
+            receive(&mut att, chunk(3, 5, 6), b"def".to_vec(), Some(&wrong)),
+            Err(Error::ChecksumMismatch)
+        ));
+        assert_eq!(UploadStatus::from(&att).received, 0);
+
+        assert!(matches!(
+            receive(&mut att, None, b"abcdef".to_vec(), Some(&wrong)),
+            Err(Error::ChecksumMismatch)
+        ));
+        assert!(
+            receive(&mut att, None, b"abcdef".to_vec(), None)
+                .unwrap()
+                .complete
+        );
+    }
+
+    #[test]
+    fn total_size_limit() {
+        let limits = UploadConfig {
+            max_file_size: 10,
+            max_total_size: 15,
+            max_files: 20,
+        };
+
+        let mut stored = attachment();
+        stored.content = Some(vec![0; 5]);
+        let mut partial = attachment();
+        partial.partial = Some(PartialUpload {
+            data: vec![0; 2],
+            total: 8,
+        });
+        let reserved = reserved_size(&stored) + reserved_size(&partial);
+        assert_eq!(reserved, 13);
+
+        assert!(check_total_size(&limits, reserved, 2).is_ok());
+        assert!(matches!(
+            check_total_size(&limits, reserved, 3),
+            Err(Error::BodyTooLarge)
+        ));
+    }
 
-    Ok(Empty)
+    #[test]
+    fn chunk_must_match_range() {
+        let mut att = attachment();
+        assert!(matches!(
+            receive(&mut att, chunk(0, 9, 20), b"short".to_vec(), None),
+            Err(Error::InvalidContentRange)
+        ));
+    }
 }
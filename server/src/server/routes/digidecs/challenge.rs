@@ -0,0 +1,17 @@
+use actix_web::web;
+use tracing::instrument;
+
+use crate::server::bot::Challenge;
+use crate::server::types::WRuntime;
+
+/// Get a challenge to submit with the form. Request it when the form is loaded,
+/// submissions made sooner than `min_fill_secs` after are rejected.
+#[utoipa::path(
+    get,
+    path = "/api/digidecs/challenge",
+    responses((status = 200, description = "A new challenge", body = Challenge)),
+)]
+#[instrument(skip_all)]
+pub async fn challenge(runtime: WRuntime) -> web::Json<Challenge> {
+    web::Json(runtime.bot_protection.issue())
+}
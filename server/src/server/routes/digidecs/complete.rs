@@ -1,23 +1,56 @@
-use crate::email::template::{render_submitter, render_treasurer, SubmitterData, TreasurerData};
-use crate::email::{send_submitter_email, send_treasurer_email, EmailLanguage, TreasurerEmailData};
-use crate::server::types::{Empty, Error, Locale, WArgs, WConfig, WResult, WRuntime};
-use actix_web::web;
-use serde::Deserialize;
-use time::OffsetDateTime;
-use tracing::{info, instrument, trace};
-
-#[derive(Deserialize)]
+use crate::email::routing::{route, Recipients};
+use crate::email::template::{
+    mask_iban, render_submitter, render_treasurer, DownloadLink, SubmitterData, TreasurerData,
+};
+use crate::email::{
+    exceeds_size_limit, send_email, submitter_email, treasurer_email, Attachment,
+    SubmitterEmailData, TreasurerEmailData,
+};
+use crate::file::AppConfig;
+use crate::i18n::{Catalog, Locale};
+use crate::server::localize::set_locale;
+use crate::server::routes::digidecs::download::download_url;
+use crate::server::types::{
+    Error, PendingDigidecsData, RuntimeData, WArgs, WConfig, WResult, WRuntime,
+};
+use crate::storage::{Declaration, DeclarationStatus, Reference, StatusChange, StoredAttachment};
+use actix_web::{web, HttpRequest};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tracing::{info, instrument, trace, warn};
+use utoipa::{IntoParams, ToSchema};
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Query {
     tracking_id: String,
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct CompleteDigidecsResponse {
+    #[schema(value_type = String, example = "DD-2026-0042")]
+    pub reference: Reference,
+}
+
+/// Complete a digidecs once all attachments are uploaded, sending it to the treasurer
+#[utoipa::path(
+    post,
+    path = "/api/digidecs/complete",
+    params(Query),
+    responses((
+            status = 200,
+            description = "The digidecs was sent",
+            body = CompleteDigidecsResponse
+        ), Error),
+)]
 #[instrument(skip_all)]
 pub async fn complete(
+    req: HttpRequest,
     query: web::Query<Query>,
     config: WConfig,
     runtime: WRuntime,
     args: WArgs,
-) -> WResult<Empty> {
+) -> WResult<web::Json<CompleteDigidecsResponse>> {
     let mut lock = runtime.pending_digidecs.lock().await;
     let (idx, _) = lock
         .iter()
@@ -25,95 +58,388 @@ pub async fn complete(
         .find(|(_, digidecs)| digidecs.tracking_id.eq(&query.tracking_id))
         .ok_or(Error::UnknownTrackingId)?;
 
-    let digidecs = lock.remove(idx);
+    set_locale(&req, lock[idx].data.locale.clone());
 
-    if digidecs.expires_at <= OffsetDateTime::now_utc() {
+    if lock[idx].expires_at <= OffsetDateTime::now_utc() {
+        lock.remove(idx);
         return Err(Error::DigidecsExpired);
     }
 
-    let attachments_cnt = digidecs
+    let attachments_cnt = lock[idx]
         .attachments
         .iter()
         .filter(|att| att.content.is_some())
         .count();
 
-    if digidecs.attachment_count != attachments_cnt {
+    // Keep the digidecs, so the missing attachments can still be uploaded
+    if lock[idx].attachment_count != attachments_cnt {
         return Err(Error::MissingAttachment);
     }
 
-    let treasurer = render_treasurer(&TreasurerData {
-        name: digidecs.data.name.clone(),
-        iban: digidecs.data.iban.clone(),
-        email: digidecs.data.email.clone(),
-        address: digidecs.data.address.clone(),
-        value: format!("{:.2}", digidecs.data.value),
-        what: digidecs.data.what.clone(),
-        commission: digidecs.data.commission.clone(),
-        notes: digidecs.data.notes.clone(),
-    })?;
+    // Removed while it is delivered, so it cannot be completed twice
+    let digidecs = lock.remove(idx);
+    drop(lock);
+
+    let attachments = digidecs
+        .attachments
+        .iter()
+        .map(|att| Attachment {
+            name: att.name.clone(),
+            mime: att.mime.clone(),
+            content: att.content.clone().unwrap(), // Some state is checked when checking if every attachment has content set earier
+        })
+        .collect::<Vec<_>>();
+
+    match deliver(
+        &config,
+        &runtime,
+        args.dry_run,
+        digidecs.data.clone(),
+        attachments,
+    )
+    .await
+    {
+        Ok(reference) => Ok(web::Json(CompleteDigidecsResponse { reference })),
+        Err(e) => {
+            // The treasurer did not receive the digidecs, allow the client to try again
+            runtime.pending_digidecs.lock().await.push(digidecs);
+            Err(e)
+        }
+    }
+}
+
+/// Store the declaration and send the emails to the treasurer and the submitter.
+/// Returns the reference assigned to the declaration.
+///
+/// If the email to the treasurer could not be sent, the stored declaration is marked
+/// [DeclarationStatus::Undelivered] and an error is returned. If only the confirmation
+/// to the submitter could not be sent, this is recorded in the history of the declaration,
+/// but the digidecs is delivered.
+pub async fn deliver(
+    config: &AppConfig,
+    runtime: &RuntimeData,
+    dry_run: bool,
+    data: PendingDigidecsData,
+    attachments: Vec<Attachment>,
+) -> WResult<Reference> {
+    let reference = if dry_run {
+        // A throwaway reference, a dry run must not advance the reference counter
+        Reference {
+            year: OffsetDateTime::now_utc().year(),
+            number: 0,
+        }
+    } else {
+        runtime.storage.next_reference().await?
+    };
+    info!("Assigned reference {reference} to digidecs");
+
+    let recipients = route(config, &data.commission, data.value);
+    match &recipients.rule {
+        Some(rule) => info!(
+            "Routing rule '{rule}' matched, sending to {:?} (cc {:?}, bcc {:?})",
+            recipients.to, recipients.cc, recipients.bcc
+        ),
+        None => info!("No routing rule matched, sending to {:?}", recipients.to),
+    }
+
+    // Store the declaration before sending, so that download links work
+    // if the attachments turn out to be too large to be sent by email
+    let declaration = Declaration {
+        reference,
+        submitted_at: OffsetDateTime::now_utc(),
+        name: data.name.clone(),
+        iban: data.iban.clone(),
+        email: data.email.clone(),
+        address: data.address.clone(),
+        value: data.value,
+        what: data.what.clone(),
+        commission: data.commission.clone(),
+        notes: data.notes.clone(),
+        attachments: attachments
+            .iter()
+            .map(|att| StoredAttachment {
+                name: att.name.clone(),
+                mime: att.mime.clone(),
+            })
+            .collect(),
+        recipients: recipients.clone(),
+        status: DeclarationStatus::default(),
+        history: vec![],
+    };
+
+    if dry_run {
+        info!(
+            "Dry run is enabled. Not storing declaration: \n{}",
+            serde_json::to_string_pretty(&declaration).unwrap_or_default()
+        );
+    } else {
+        runtime.storage.save_declaration(&declaration).await?;
+        for (idx, att) in attachments.iter().enumerate() {
+            runtime
+                .storage
+                .save_attachment(&reference, idx, &att.content)
+                .await?;
+        }
+    }
+
+    let treasurer_locale = runtime.catalog.default_locale().clone();
+    let submitter_locale = runtime.catalog.resolve(&data.locale);
+
+    let mut treasurer_data = TreasurerData {
+        locale: treasurer_locale.clone(),
+        reference: reference.to_string(),
+        name: data.name.clone(),
+        iban: data.iban.clone(),
+        email: data.email.clone(),
+        address: data.address.clone(),
+        value: format!("{:.2}", data.value),
+        what: data.what.clone(),
+        commission: data.commission.clone(),
+        notes: data.notes.clone(),
+        download_links: vec![],
+        download_links_expire_at: None,
+    };
+
+    let build_treasurer_email = |body: &str, attachments: Vec<Attachment>| {
+        treasurer_email(
+            &config.smtp,
+            treasurer_email_data(
+                &runtime.catalog,
+                &treasurer_locale,
+                &reference,
+                &recipients,
+                body,
+                &data,
+                attachments,
+            ),
+        )
+    };
+
+    let mut treasurer = render_treasurer(&runtime.templates, &treasurer_data)?;
+    let mut treasurer_msg = build_treasurer_email(&treasurer, attachments.clone())?;
+
+    if exceeds_size_limit(&config.smtp, &treasurer_msg) {
+        info!("Email to treasurer is too large, replacing attachments with download links");
+
+        let expires =
+            OffsetDateTime::now_utc() + Duration::days(config.server.download_link_validity_days);
+        treasurer_data.download_links = attachments
+            .iter()
+            .enumerate()
+            .map(|(idx, att)| DownloadLink {
+                name: att.name.clone(),
+                url: download_url(config, &runtime.signer, reference, idx, expires),
+            })
+            .collect();
+        treasurer_data.download_links_expire_at = Some(format!(
+            "{:02}-{:02}-{}",
+            expires.day(),
+            u8::from(expires.month()),
+            expires.year()
+        ));
+
+        treasurer = render_treasurer(&runtime.templates, &treasurer_data)?;
+        treasurer_msg = build_treasurer_email(&treasurer, vec![])?;
+    }
 
     let submitter = render_submitter(
+        &runtime.templates,
         &SubmitterData {
-            first_name: digidecs
-                .data
+            locale: submitter_locale.clone(),
+            first_name: data
                 .name
                 .split(" ")
                 .collect::<Vec<_>>()
                 .first()
                 .map(|s| s.to_string())
-                .unwrap_or(digidecs.data.name.clone()),
+                .unwrap_or(data.name.clone()),
+            reference: reference.to_string(),
+            value: format!("{:.2}", data.value),
+            what: data.what.clone(),
+            commission: data.commission.clone(),
+            iban: mask_iban(&data.iban),
+            attachments: attachments.iter().map(|att| att.name.clone()).collect(),
         },
-        &map_locale_to_email_lang(&digidecs.data.locale),
     )?;
 
-    let attachments = digidecs
-        .attachments
-        .into_iter()
-        .map(|att| crate::email::Attachment {
-            name: att.name,
-            mime: att.mime,
-            content: att.content.unwrap(), // Some state is checked when checking if every attachment has content set earier
-        })
-        .collect::<Vec<_>>();
+    let build_submitter_email = |attachments: Vec<Attachment>| {
+        submitter_email(
+            &config.smtp,
+            SubmitterEmailData {
+                catalog: &runtime.catalog,
+                reference: &reference,
+                to_email: &data.email,
+                name: &data.name,
+                body: submitter.clone(),
+                locale: &submitter_locale,
+                commission: &data.commission,
+                attachments,
+            },
+        )
+    };
+
+    let mut submitter_msg = if config.submitter_attachments {
+        build_submitter_email(attachments)?
+    } else {
+        build_submitter_email(vec![])?
+    };
 
-    if args.dry_run {
+    if exceeds_size_limit(&config.smtp, &submitter_msg) {
+        info!("Email to submitter is too large, not including attachments");
+        submitter_msg = build_submitter_email(vec![])?;
+    }
+
+    if dry_run {
         info!("Dry run is enabled. Not sending email.");
         info!("Email body to treasurer: \n{treasurer}");
         info!("Email body to submitter: \n{submitter}");
     } else {
         trace!("Sending Digidecs email to treasurer");
-        send_treasurer_email(
+        let timer = runtime.metrics.smtp_send_duration.start_timer();
+        let sent = send_email(
             &config.smtp,
-            runtime.local_v4_addr,
-            TreasurerEmailData {
-                to: &config.treasurer_email,
-                body: &treasurer,
-                reply_to_name: &digidecs.data.name,
-                reply_to_email: &digidecs.data.email,
-                commission: &digidecs.data.what.clone(),
-                attachments,
-            },
+            runtime.source_addrs.current(),
+            runtime.dkim.as_deref(),
+            treasurer_msg,
         )
-        .await?;
+        .await;
+        timer.observe_duration();
+
+        if let Err(e) = sent {
+            record_failure(
+                runtime,
+                &reference,
+                DeclarationStatus::Undelivered,
+                format!("Sending the email to the treasurer failed: {e}"),
+            )
+            .await;
+            return Err(e.into());
+        }
 
         trace!("Sending DigiDecs email to submitter");
-        send_submitter_email(
+        let timer = runtime.metrics.smtp_send_duration.start_timer();
+        let sent = send_email(
             &config.smtp,
-            runtime.local_v4_addr,
-            &digidecs.data.email,
-            submitter,
-            &digidecs.data.name,
-            &map_locale_to_email_lang(&digidecs.data.locale),
+            runtime.source_addrs.current(),
+            runtime.dkim.as_deref(),
+            submitter_msg,
         )
-        .await?;
+        .await;
+        timer.observe_duration();
+
+        // The treasurer has the digidecs, it must not be submitted again
+        if let Err(e) = sent {
+            record_failure(
+                runtime,
+                &reference,
+                DeclarationStatus::Submitted,
+                format!("Sending the confirmation to the submitter failed: {e}"),
+            )
+            .await;
+        }
     }
 
-    Ok(Empty)
+    Ok(reference)
+}
+
+fn treasurer_email_data<'a>(
+    catalog: &'a Catalog,
+    locale: &'a Locale,
+    reference: &'a Reference,
+    recipients: &'a Recipients,
+    body: &'a str,
+    data: &'a PendingDigidecsData,
+    attachments: Vec<Attachment>,
+) -> TreasurerEmailData<'a> {
+    TreasurerEmailData {
+        catalog,
+        locale,
+        reference,
+        recipients,
+        body,
+        reply_to_name: &data.name,
+        reply_to_email: &data.email,
+        commission: &data.commission,
+        attachments,
+    }
 }
 
-fn map_locale_to_email_lang(locale: &Locale) -> EmailLanguage {
-    match locale {
-        Locale::En => EmailLanguage::En,
-        Locale::Nl => EmailLanguage::Nl,
+/// Record a failure to send an email in the history of the stored declaration,
+/// so the treasurer can see it
+async fn record_failure(
+    runtime: &RuntimeData,
+    reference: &Reference,
+    status: DeclarationStatus,
+    note: String,
+) {
+    warn!("{reference}: {note}");
+
+    let recorded = runtime
+        .storage
+        .update_declaration(reference, |declaration| {
+            declaration.status = status;
+            declaration.history.push(StatusChange {
+                status,
+                at: OffsetDateTime::now_utc(),
+                by: "digidecs".to_string(),
+                note: Some(note),
+            });
+        })
+        .await;
+
+    if let Err(e) = recorded {
+        warn!("Failed to record the failure in declaration {reference}: {e}");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::file::SmtpConfig;
+
+    #[test]
+    fn treasurer_subject() {
+        let catalog = Catalog::load(None, Locale::new("en")).unwrap();
+        let config = SmtpConfig {
+            from_email: "digidecs@example.com".into(),
+            ..Default::default()
+        };
+        let recipients = Recipients {
+            rule: None,
+            to: vec!["treasurer@example.com".to_string()],
+            cc: vec![],
+            bcc: vec![],
+        };
+        let data = PendingDigidecsData {
+            name: "Jan Jansen".to_string(),
+            iban: "NL91ABNA0417164300".to_string(),
+            email: "jan@example.com".to_string(),
+            address: "Straat 1".to_string(),
+            value: 12.5,
+            what: "Pizza".to_string(),
+            commission: "Bestuur".to_string(),
+            notes: None,
+            locale: Locale::new("en"),
+        };
+
+        let msg = treasurer_email(
+            &config,
+            treasurer_email_data(
+                &catalog,
+                &Locale::new("en"),
+                &Reference {
+                    year: 2026,
+                    number: 42,
+                },
+                &recipients,
+                "body",
+                &data,
+                vec![],
+            ),
+        )
+        .unwrap();
+
+        let formatted = String::from_utf8(msg.formatted()).unwrap();
+        assert!(formatted.contains("Subject: [DigiDecs] New declaration DD-2026-0042: Bestuur\r\n"));
     }
 }
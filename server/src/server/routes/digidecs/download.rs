@@ -0,0 +1,113 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{web, HttpResponse};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tracing::{instrument, trace};
+use utoipa::IntoParams;
+
+use crate::file::AppConfig;
+use crate::server::types::{unknown_attachment, Error, WResult, WRuntime};
+use crate::signing::Signer;
+use crate::storage::Reference;
+
+#[derive(Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Query {
+    #[param(value_type = String, example = "DD-2026-0042")]
+    reference: Reference,
+    attachment: usize,
+    /// Unix timestamp after which the link is no longer valid
+    expires: i64,
+    signature: String,
+}
+
+impl Query {
+    fn signed_message(&self) -> String {
+        format!("{}/{}/{}", self.reference, self.attachment, self.expires)
+    }
+}
+
+/// Create a signed link to download an attachment of a stored declaration,
+/// valid until `expires`.
+pub fn download_url(
+    config: &AppConfig,
+    signer: &Signer,
+    reference: Reference,
+    attachment: usize,
+    expires: OffsetDateTime,
+) -> String {
+    let mut query = Query {
+        reference,
+        attachment,
+        expires: expires.unix_timestamp(),
+        signature: String::new(),
+    };
+    query.signature = signer.sign(&query.signed_message());
+
+    format!(
+        "{}/api/digidecs/download?reference={}&attachment={}&expires={}&signature={}",
+        config.server.public_url(),
+        query.reference,
+        query.attachment,
+        query.expires,
+        query.signature
+    )
+}
+
+/// Download an attachment of a completed declaration, with a signed link from the treasurer email
+#[utoipa::path(
+    get,
+    path = "/api/digidecs/download",
+    params(Query),
+    responses(
+        (
+            status = 200,
+            description = "The attachment",
+            content_type = "application/octet-stream",
+            body = Vec<u8>
+        ),
+        Error,
+    ),
+)]
+#[instrument(skip_all)]
+pub async fn download(query: web::Query<Query>, runtime: WRuntime) -> WResult<HttpResponse> {
+    if !runtime
+        .signer
+        .verify(&query.signed_message(), &query.signature)
+    {
+        return Err(Error::InvalidSignature);
+    }
+
+    if query.expires <= OffsetDateTime::now_utc().unix_timestamp() {
+        return Err(Error::LinkExpired);
+    }
+
+    trace!(
+        "Serving attachment {} of {}",
+        query.attachment,
+        query.reference
+    );
+
+    let declaration = runtime
+        .storage
+        .load_declaration(&query.reference)
+        .await
+        .map_err(unknown_attachment)?;
+    let attachment = declaration
+        .attachments
+        .get(query.attachment)
+        .ok_or(Error::UnknownAttachment)?;
+    let content = runtime
+        .storage
+        .read_attachment(&query.reference, query.attachment)
+        .await
+        .map_err(unknown_attachment)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(attachment.mime.as_str())
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(attachment.name.clone())],
+        })
+        .body(content))
+}
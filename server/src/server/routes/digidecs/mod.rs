@@ -2,9 +2,12 @@ use actix_route_config::Routable;
 use actix_web::web;
 use actix_web::web::ServiceConfig;
 
-mod attachment;
-mod complete;
-mod start;
+pub mod attachment;
+pub mod challenge;
+pub mod complete;
+pub mod download;
+pub mod start;
+pub mod submit;
 
 pub struct Router;
 
@@ -12,9 +15,13 @@ impl Routable for Router {
     fn configure(config: &mut ServiceConfig) {
         config.service(
             web::scope("/digidecs")
+                .route("/challenge", web::get().to(challenge::challenge))
                 .route("/start", web::post().to(start::start))
                 .route("/attachment", web::post().to(attachment::attachment))
-                .route("/complete", web::post().to(complete::complete)),
+                .route("/attachment", web::get().to(attachment::status))
+                .route("/complete", web::post().to(complete::complete))
+                .route("/download", web::get().to(download::download))
+                .route("/submit", web::post().to(submit::submit)),
         );
     }
 }
@@ -1,74 +1,109 @@
 use std::str::FromStr;
 use std::sync::OnceLock;
 
-use actix_web::web;
+use actix_web::{web, HttpRequest};
 use iban::Iban;
 use rand::Rng;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 use tracing::instrument;
+use utoipa::ToSchema;
 
+use crate::i18n::Locale;
+use crate::server::bot::ChallengeSolution;
+use crate::server::localize::set_locale;
+use crate::server::session;
 use crate::server::types::{
-    Error, Locale, PendingDigidecs, PendingDigidecsAttachment, PendingDigidecsData, WResult,
+    Error, PendingDigidecs, PendingDigidecsAttachment, PendingDigidecsData, WConfig, WResult,
     WRuntime,
 };
+use crate::server::validation::{Validate, Validator};
 
-#[derive(Deserialize)]
+/// How long a started declaration can be completed
+const PENDING_VALIDITY: Duration = Duration::hours(1);
+/// How long an expired declaration is kept
+const EXPIRED_RETENTION: Duration = Duration::hours(1);
+
+#[derive(Deserialize, ToSchema)]
 pub struct StartDigidecsRequest {
-    name: String,
-    iban: String,
-    email: String,
-    address: String,
-    value: f64,
-    what: String,
-    commission: String,
-    notes: Option<String>,
-    attachments: Vec<Attachment>,
-    locale: Locale,
+    /// Ignored if the member is logged in, the name of the login is used instead
+    #[serde(default)]
+    pub name: String,
+    pub iban: String,
+    /// Ignored if the member is logged in, the email address of the login is used instead
+    #[serde(default)]
+    pub email: String,
+    pub address: String,
+    pub value: f64,
+    pub what: String,
+    pub commission: String,
+    pub notes: Option<String>,
+    pub attachments: Vec<Attachment>,
+    /// Language of the confirmation email and of errors
+    #[schema(value_type = String, example = "nl")]
+    pub locale: Locale,
+    /// Honeypot, must be left empty. Hidden in the form, so only bots fill it in.
+    #[serde(default)]
+    pub website: Option<String>,
+    /// The solved challenge from `/api/digidecs/challenge`, if bot protection requires one
+    #[serde(default)]
+    pub challenge: Option<ChallengeSolution>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct Attachment {
-    name: String,
-    mime: String,
+    pub name: String,
+    pub mime: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct StartDigidecsResponse {
     tracking_id: String,
     attachments: Vec<AttachmentResponse>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct AttachmentResponse {
     name: String,
     mime: String,
     tracking_id: String,
 }
 
+/// Start a digidecs. The attachments are uploaded separately, with the returned tracking IDs
+#[utoipa::path(
+    post,
+    path = "/api/digidecs/start",
+    request_body = StartDigidecsRequest,
+    responses((status = 200, description = "The digidecs was started", body = StartDigidecsResponse), Error),
+)]
 #[instrument(skip_all)]
 pub async fn start(
+    req: HttpRequest,
     payload: web::Json<StartDigidecsRequest>,
+    config: WConfig,
     runtime: WRuntime,
 ) -> WResult<web::Json<StartDigidecsResponse>> {
-    let payload = payload.into_inner();
-
-    if !validate_email(&payload.email) {
-        return Err(Error::InvalidEmail);
-    }
-
-    if !validate_iban(&payload.iban) {
-        return Err(Error::InvalidIban);
-    }
-
-    if payload.attachments.is_empty() {
-        return Err(Error::MissingAttachment);
-    }
-
-    if payload.value <= 0.0 {
-        return Err(Error::ValueNegativeOrZero);
+    let mut payload = payload.into_inner();
+    set_locale(&req, payload.locale.clone());
+
+    runtime.rate_limiter.check_ip(&req)?;
+    if let Some(identity) = session::identity(&req, &config, &runtime)? {
+        payload.name = identity.name;
+        payload.email = identity.email;
+        if payload.address.trim().is_empty() {
+            payload.address = identity.address.unwrap_or_default();
+        }
     }
+    payload.validate()?;
+    runtime
+        .bot_protection
+        .verify_and_consume(payload.website.as_deref(), payload.challenge.as_ref())?;
+    let release_challenge = |_: &Error| runtime.bot_protection.release(payload.challenge.as_ref());
+    runtime
+        .rate_limiter
+        .check_email(&payload.email)
+        .inspect_err(release_challenge)?;
 
     let tracking_id = gen_tracking_id();
     let attachment_tracking_ids = payload
@@ -83,8 +118,23 @@ pub async fn start(
 
     let mut lock = runtime.pending_digidecs.lock().await;
 
+    let now = OffsetDateTime::now_utc();
+    // Expired declarations are kept for a while, so completing one reports it expired
+    lock.retain(|digidecs| digidecs.expires_at + EXPIRED_RETENTION > now);
+
+    let active = lock.iter().filter(|digidecs| digidecs.expires_at > now);
+    if active.clone().count() >= config.rate_limit.max_pending {
+        let retry_after = active
+            .map(|digidecs| digidecs.expires_at - now)
+            .min()
+            .unwrap_or(PENDING_VALIDITY);
+        let error = Error::RateLimited(retry_after.try_into().unwrap_or_default());
+        release_challenge(&error);
+        return Err(error);
+    }
+
     lock.push(PendingDigidecs {
-        expires_at: OffsetDateTime::now_utc() + Duration::hours(1),
+        expires_at: now + PENDING_VALIDITY,
         tracking_id: tracking_id.clone(),
         attachment_count: attachment_tracking_ids.len(),
         attachments: attachment_tracking_ids
@@ -93,6 +143,7 @@ pub async fn start(
                 name: att.name.clone(),
                 tracking_id: att.tracking_id.clone(),
                 content: None,
+                partial: None,
                 mime: att.mime.clone(),
             })
             .collect(),
@@ -115,6 +166,18 @@ pub async fn start(
     }))
 }
 
+impl Validate for StartDigidecsRequest {
+    fn validate(&self) -> WResult<()> {
+        Validator::new()
+            .check(validate_email(&self.email), Error::InvalidEmail)
+            .check(validate_iban(&self.iban), Error::InvalidIban)
+            .check(!self.address.trim().is_empty(), Error::InvalidAddress)
+            .check(!self.attachments.is_empty(), Error::MissingAttachment)
+            .check(self.value > 0.0, Error::ValueNegativeOrZero)
+            .finish()
+    }
+}
+
 static EMAIL_REGEX: OnceLock<Regex> = OnceLock::new();
 
 fn gen_tracking_id() -> String {
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::http::header::ACCEPT_LANGUAGE;
+use actix_web::{web, HttpRequest};
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+use tracing::{instrument, trace};
+use utoipa::ToSchema;
+
+use crate::email::Attachment;
+use crate::file::UploadConfig;
+use crate::i18n::Locale;
+use crate::server::bot::ChallengeSolution;
+use crate::server::localize::set_locale;
+use crate::server::routes::digidecs::complete::{deliver, CompleteDigidecsResponse};
+use crate::server::routes::digidecs::start::{self, StartDigidecsRequest};
+use crate::server::session;
+use crate::server::types::{Error, PendingDigidecsData, WArgs, WConfig, WResult, WRuntime};
+use crate::server::validation::{Validate, Validator};
+use crate::storage::Storage;
+
+/// Form fields which must be present
+const REQUIRED_FIELDS: &[&str] = &[
+    "name",
+    "iban",
+    "email",
+    "address",
+    "value",
+    "what",
+    "commission",
+];
+
+/// The largest accepted text field, in bytes
+const MAX_FIELD_SIZE: usize = 64 * 1024;
+/// The most text fields accepted, well above the number of fields of the form
+const MAX_TEXT_FIELDS: usize = 32;
+
+/// The `multipart/form-data` request accepted by [submit]. Only used for documentation.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+pub struct SubmitForm {
+    name: String,
+    iban: String,
+    email: String,
+    address: String,
+    value: f64,
+    what: String,
+    commission: String,
+    notes: Option<String>,
+    /// Language of the confirmation email and of errors.
+    /// Defaults to the language from `Accept-Language`.
+    locale: Option<String>,
+    /// Honeypot, must be left empty
+    website: Option<String>,
+    /// The token of the challenge from `/api/digidecs/challenge`, if bot protection requires one
+    challenge_token: Option<String>,
+    /// The solution to the proof of work of the challenge
+    challenge_solution: Option<String>,
+    /// One or more files
+    #[schema(value_type = Vec<String>, format = Binary)]
+    attachments: Vec<Vec<u8>>,
+}
+
+/// A file part, written to the spool directory while it is received.
+/// The file is removed when dropped.
+struct Spooled {
+    name: String,
+    mime: String,
+    path: PathBuf,
+    size: u64,
+}
+
+impl Drop for Spooled {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Submit a digidecs with all its attachments in one request,
+/// for scripts and HTML forms. Equivalent to start, attachment and complete.
+#[utoipa::path(
+    post,
+    path = "/api/digidecs/submit",
+    request_body(content = SubmitForm, content_type = "multipart/form-data"),
+    responses(
+        (
+            status = 200,
+            description = "The digidecs was sent",
+            body = CompleteDigidecsResponse
+        ),
+        Error,
+    ),
+)]
+#[instrument(skip_all)]
+pub async fn submit(
+    req: HttpRequest,
+    mut payload: Multipart,
+    config: WConfig,
+    runtime: WRuntime,
+    args: WArgs,
+) -> WResult<web::Json<CompleteDigidecsResponse>> {
+    runtime.rate_limiter.check_ip(&req)?;
+    let identity = session::identity(&req, &config, &runtime)?;
+
+    let (mut fields, files) = read_form(&mut payload, &runtime.storage, &config.uploads).await?;
+    for file in &files {
+        runtime.metrics.upload_size.observe(file.size as f64);
+    }
+
+    let locale = match fields.get("locale") {
+        Some(locale) => Locale::new(locale),
+        None => req
+            .headers()
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| runtime.catalog.negotiate(value))
+            .unwrap_or_else(|| runtime.catalog.default_locale().clone()),
+    };
+    set_locale(&req, locale.clone());
+
+    if let Some(identity) = identity {
+        fields.insert("name".to_string(), identity.name);
+        fields.insert("email".to_string(), identity.email);
+        if let Some(address) = identity.address {
+            let address_field = fields.entry("address".to_string()).or_default();
+            if address_field.trim().is_empty() {
+                *address_field = address;
+            }
+        }
+    }
+
+    let mut validator = Validator::new();
+    for field in REQUIRED_FIELDS {
+        validator.check(fields.contains_key(*field), Error::InvalidField(field));
+    }
+
+    let value = fields.get("value").map(|value| parse_value(value));
+    validator.check(!matches!(value, Some(None)), Error::InvalidField("value"));
+
+    let mut field = |name: &str| fields.remove(name).unwrap_or_default();
+    let request = StartDigidecsRequest {
+        name: field("name"),
+        iban: field("iban"),
+        email: field("email"),
+        address: field("address"),
+        value: value.flatten().unwrap_or_default(),
+        what: field("what"),
+        commission: field("commission"),
+        notes: Some(field("notes")).filter(|notes| !notes.trim().is_empty()),
+        attachments: files
+            .iter()
+            .map(|file| start::Attachment {
+                name: file.name.clone(),
+                mime: file.mime.clone(),
+            })
+            .collect(),
+        locale,
+        website: fields.remove("website"),
+        challenge: fields
+            .remove("challenge_token")
+            .map(|token| ChallengeSolution {
+                token,
+                solution: fields.remove("challenge_solution").unwrap_or_default(),
+            }),
+    };
+    validator.merge(request.validate()).finish()?;
+    runtime
+        .bot_protection
+        .verify_and_consume(request.website.as_deref(), request.challenge.as_ref())?;
+    let release_challenge = |_: &Error| runtime.bot_protection.release(request.challenge.as_ref());
+    runtime
+        .rate_limiter
+        .check_email(&request.email)
+        .inspect_err(release_challenge)?;
+
+    trace!("Received digidecs with {} attachments", files.len());
+
+    // Sending the email requires the attachments in memory,
+    // `uploads.max_total_size` bounds how much that is
+    let mut attachments = vec![];
+    for file in &files {
+        attachments.push(Attachment {
+            name: file.name.clone(),
+            mime: file.mime.clone(),
+            content: tokio::fs::read(&file.path)
+                .await
+                .map_err(crate::storage::StorageError::from)?,
+        });
+    }
+
+    let data = PendingDigidecsData {
+        name: request.name,
+        iban: request.iban,
+        email: request.email,
+        address: request.address,
+        value: request.value,
+        what: request.what,
+        commission: request.commission,
+        notes: request.notes,
+        locale: request.locale,
+    };
+
+    let reference = deliver(&config, &runtime, args.dry_run, data, attachments)
+        .await
+        .inspect_err(release_challenge)?;
+    Ok(web::Json(CompleteDigidecsResponse { reference }))
+}
+
+/// Read the text fields and spool the files of the form, enforcing the upload limits.
+/// File inputs left empty, which browsers send as a part without a filename or content, are skipped.
+async fn read_form(
+    payload: &mut Multipart,
+    storage: &Storage,
+    limits: &UploadConfig,
+) -> WResult<(HashMap<String, String>, Vec<Spooled>)> {
+    let mut fields = HashMap::new();
+    let mut files = vec![];
+    // Bytes the files may still take up, counted while they are written
+    let mut remaining = limits.max_total_size;
+
+    while let Some(field) = payload.next().await {
+        let field = field?;
+        let Some(name) = field.name().map(str::to_string) else {
+            continue;
+        };
+
+        let is_file = field
+            .content_disposition()
+            .is_some_and(|cd| cd.get_filename().is_some());
+        if is_file {
+            if files.len() >= limits.max_files {
+                return Err(Error::BodyTooLarge);
+            }
+
+            let limit = remaining.min(limits.max_file_size);
+            let file = spool(field, storage, limit).await?;
+            if file.name.is_empty() && file.size == 0 {
+                continue;
+            }
+
+            remaining -= file.size;
+            files.push(file);
+        } else {
+            if fields.len() >= MAX_TEXT_FIELDS {
+                return Err(Error::BodyTooLarge);
+            }
+            fields.insert(name, read_text(field).await?);
+        }
+    }
+
+    Ok((fields, files))
+}
+
+/// Write a file part to the spool directory, chunk by chunk.
+/// Stops with [Error::BodyTooLarge] as soon as the file exceeds `limit` bytes.
+async fn spool(mut field: Field, storage: &Storage, limit: u64) -> WResult<Spooled> {
+    let (path, mut file) = storage.create_spool_file().await?;
+    let mut spooled = Spooled {
+        name: field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .unwrap_or_default()
+            .to_string(),
+        mime: field
+            .content_type()
+            .map_or("application/octet-stream".to_string(), |mime| {
+                mime.to_string()
+            }),
+        path,
+        size: 0,
+    };
+
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk?;
+        spooled.size += chunk.len() as u64;
+        if spooled.size > limit {
+            return Err(Error::BodyTooLarge);
+        }
+
+        file.write_all(&chunk)
+            .await
+            .map_err(crate::storage::StorageError::from)?;
+    }
+    file.flush()
+        .await
+        .map_err(crate::storage::StorageError::from)?;
+
+    Ok(spooled)
+}
+
+async fn read_text(mut field: Field) -> WResult<String> {
+    let name = field.name().unwrap_or_default().to_string();
+    let mut text = vec![];
+
+    while let Some(chunk) = field.next().await {
+        text.extend_from_slice(&chunk?);
+        if text.len() > MAX_FIELD_SIZE {
+            return Err(Error::BodyTooLarge);
+        }
+    }
+
+    String::from_utf8(text).map_err(|_| match REQUIRED_FIELDS.iter().find(|f| **f == name) {
+        Some(field) => Error::InvalidField(field),
+        None => Error::InvalidField("form"),
+    })
+}
+
+/// Parse an amount, accepting both `12.50` and `12,50`.
+/// At most two decimals are accepted, and no infinite amounts such as `inf` or `1e400`.
+fn parse_value(value: &str) -> Option<f64> {
+    let value = value.trim().replace(',', ".");
+    if value
+        .split_once('.')
+        .is_some_and(|(_, decimals)| decimals.len() > 2)
+    {
+        return None;
+    }
+
+    value.parse().ok().filter(|v: &f64| v.is_finite())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use actix_web::http::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+    use actix_web::web::Bytes;
+
+    #[test]
+    fn value_with_comma() {
+        assert_eq!(parse_value("12,50"), Some(12.5));
+        assert_eq!(parse_value(" 12.50 "), Some(12.5));
+        assert_eq!(parse_value("twelve"), None);
+    }
+
+    #[test]
+    fn value_must_be_finite() {
+        for value in ["inf", "infinity", "-inf", "NaN", "1e400", "12.505"] {
+            assert_eq!(parse_value(value), None, "{value}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn skips_empty_file_inputs() {
+        let root = std::env::temp_dir().join(format!("digidecs-test-{}", rand::random::<u64>()));
+        let storage = Storage::open(&root).await.unwrap();
+
+        // As sent by a browser for a file input without a selected file
+        let body = concat!(
+            "--boundary\r\n",
+            "Content-Disposition: form-data; name=\"name\"\r\n\r\n",
+            "Jan Jansen\r\n",
+            "--boundary\r\n",
+            "Content-Disposition: form-data; name=\"attachments\"; filename=\"\"\r\n",
+            "Content-Type: application/octet-stream\r\n\r\n",
+            "\r\n",
+            "--boundary\r\n",
+            "Content-Disposition: form-data; name=\"attachments\"; filename=\"bon.pdf\"\r\n",
+            "Content-Type: application/pdf\r\n\r\n",
+            "%PDF\r\n",
+            "--boundary--\r\n",
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("multipart/form-data; boundary=boundary"),
+        );
+        let mut payload = Multipart::new(
+            &headers,
+            futures_util::stream::once(async move { Ok(Bytes::from_static(body.as_bytes())) }),
+        );
+
+        let (fields, files) = read_form(&mut payload, &storage, &UploadConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(fields.get("name").map(String::as_str), Some("Jan Jansen"));
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "bon.pdf");
+        assert_eq!(files[0].size, 4);
+
+        drop(files);
+        tokio::fs::remove_dir_all(root).await.unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use actix_web::HttpResponse;
+use serde::Serialize;
+use tracing::{instrument, warn};
+
+use crate::email::check_relay;
+use crate::server::types::{WArgs, WConfig, WRuntime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Fail,
+    /// The check does not apply, e.g. SMTP in dry-run mode
+    Skipped,
+}
+
+/// How long the result of the SMTP check is reused,
+/// so frequent readiness checks do not each connect to the relay
+const SMTP_CHECK_CACHE: Duration = Duration::from_secs(30);
+
+/// Only the status is exposed, the errors are logged.
+/// They contain paths and hostnames that are of no business to anonymous callers.
+#[derive(Serialize)]
+pub struct Check {
+    status: Status,
+}
+
+impl Check {
+    fn new<E: Display>(name: &str, result: Result<(), E>) -> Self {
+        match result {
+            Ok(()) => Self { status: Status::Ok },
+            Err(e) => {
+                warn!("Readiness check {name} failed: {e}");
+                Self {
+                    status: Status::Fail,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    status: Status,
+    checks: BTreeMap<&'static str, Check>,
+}
+
+/// Liveness: the process is up and handling requests
+pub async fn health() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": Status::Ok }))
+}
+
+/// Readiness: every dependency needed to deliver a declaration works.
+/// Responds with 503 if any check fails.
+#[instrument(skip_all)]
+pub async fn ready(config: WConfig, runtime: WRuntime, args: WArgs) -> HttpResponse {
+    let smtp = async {
+        if args.dry_run {
+            return Check {
+                status: Status::Skipped,
+            };
+        }
+
+        // Held during the check, so concurrent requests wait for its result
+        let mut cached = runtime.smtp_check.lock().await;
+        let ok = match *cached {
+            Some((checked_at, ok)) if checked_at.elapsed() < SMTP_CHECK_CACHE => ok,
+            _ => {
+                let result = check_relay(&config.smtp, runtime.source_addrs.current()).await;
+                let ok = Check::new("smtp", result).status == Status::Ok;
+                *cached = Some((Instant::now(), ok));
+                ok
+            }
+        };
+
+        Check {
+            status: if ok { Status::Ok } else { Status::Fail },
+        }
+    };
+
+    let (storage, spool, smtp) = tokio::join!(
+        runtime.storage.check_declarations(),
+        runtime.storage.check_spool_writable(),
+        smtp,
+    );
+
+    let checks = BTreeMap::from([
+        (
+            "templates",
+            Check::new("templates", runtime.templates.verify()),
+        ),
+        ("storage", Check::new("storage", storage)),
+        ("spool", Check::new("spool", spool)),
+        ("smtp", smtp),
+    ]);
+
+    let failed = checks
+        .iter()
+        .filter(|(_, check)| check.status == Status::Fail)
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+
+    if failed.is_empty() {
+        HttpResponse::Ok().json(ReadyResponse {
+            status: Status::Ok,
+            checks,
+        })
+    } else {
+        warn!("Not ready, failed checks: {}", failed.join(", "));
+        HttpResponse::ServiceUnavailable().json(ReadyResponse {
+            status: Status::Fail,
+            checks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn check_from_result() {
+        let ok = Check::new("smtp", Ok::<(), String>(()));
+        assert_eq!(ok.status, Status::Ok);
+
+        let fail = Check::new("smtp", Err::<(), _>("Relay smtp.example.com unreachable"));
+        assert_eq!(fail.status, Status::Fail);
+        // The error is not exposed
+        assert_eq!(
+            serde_json::to_value(&fail).unwrap(),
+            serde_json::json!({"status": "fail"})
+        );
+    }
+}
@@ -0,0 +1,16 @@
+use actix_web::HttpResponse;
+
+use crate::server::types::WRuntime;
+
+pub async fn metrics(runtime: WRuntime) -> HttpResponse {
+    runtime
+        .metrics
+        .observe_pending(&runtime.pending_digidecs.lock().await);
+
+    match runtime.metrics.encode() {
+        Ok(body) => HttpResponse::Ok()
+            .content_type(prometheus::TEXT_FORMAT)
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
@@ -2,12 +2,27 @@ use actix_route_config::Routable;
 use actix_web::web;
 use actix_web::web::ServiceConfig;
 
+mod admin;
+mod auth;
 mod digidecs;
+mod health;
+mod metrics;
+mod openapi;
 
 pub struct Router;
 
 impl Routable for Router {
     fn configure(config: &mut ServiceConfig) {
-        config.service(web::scope("/api").configure(digidecs::Router::configure));
+        config
+            .route("/health", web::get().to(health::health))
+            .route("/ready", web::get().to(health::ready))
+            .route("/metrics", web::get().to(metrics::metrics))
+            .service(
+                web::scope("/api")
+                    .route("/openapi.json", web::get().to(openapi::openapi))
+                    .configure(admin::Router::configure)
+                    .configure(auth::Router::configure)
+                    .configure(digidecs::Router::configure),
+            );
     }
 }
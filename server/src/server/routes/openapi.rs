@@ -0,0 +1,86 @@
+use actix_web::web;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::server::routes::{admin, auth, digidecs};
+use crate::server::session::SESSION_COOKIE;
+use crate::server::types::ErrorBody;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "DigiDecs"),
+    paths(
+        digidecs::challenge::challenge,
+        digidecs::start::start,
+        digidecs::attachment::attachment,
+        digidecs::attachment::status,
+        digidecs::complete::complete,
+        digidecs::download::download,
+        digidecs::submit::submit,
+        auth::login,
+        auth::callback,
+        auth::session,
+        auth::logout,
+        admin::list::list,
+        admin::details::details,
+        admin::attachment::attachment,
+        admin::status::status,
+    ),
+    components(schemas(ErrorBody)),
+    modifiers(&AdminSecurity)
+)]
+pub struct ApiDoc;
+
+/// The ways to authenticate to the admin API
+struct AdminSecurity;
+
+impl Modify for AdminSecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_token",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(SESSION_COOKIE))),
+        );
+    }
+}
+
+pub async fn openapi() -> web::Json<utoipa::openapi::OpenApi> {
+    web::Json(ApiDoc::openapi())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn documents_routes_and_errors() {
+        let doc = ApiDoc::openapi();
+
+        let start = doc.paths.paths.get("/api/digidecs/start").unwrap();
+        let responses = &start.post.as_ref().unwrap().responses.responses;
+        for status in ["200", "400", "404", "413", "500"] {
+            assert!(responses.contains_key(status), "Missing status {status}");
+        }
+
+        let admin = doc.paths.paths.get("/api/admin/declarations").unwrap();
+        let responses = &admin.get.as_ref().unwrap().responses.responses;
+        for status in ["200", "401", "403"] {
+            assert!(responses.contains_key(status), "Missing status {status}");
+        }
+
+        let components = doc.components.unwrap();
+        assert!(components.security_schemes.contains_key("api_token"));
+        let schemas = components.schemas;
+        for schema in [
+            "ErrorBody",
+            "StartDigidecsRequest",
+            "CompleteDigidecsResponse",
+        ] {
+            assert!(schemas.contains_key(schema), "Missing schema {schema}");
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::HttpRequest;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::file::AppConfig;
+use crate::oidc::Identity;
+use crate::server::types::{Error, RuntimeData, WResult};
+use crate::signing::Signer;
+
+pub const SESSION_COOKIE: &str = "digidecs_session";
+/// Holds the login in progress, until the issuer redirects back
+pub const LOGIN_COOKIE: &str = "digidecs_login";
+
+/// A logged in member. Stored in a signed cookie, so no state is kept on the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub identity: Identity,
+    /// Unix timestamp after which the session is no longer valid
+    pub expires: i64,
+}
+
+impl Session {
+    /// The session of the request, if it has a valid one
+    pub fn from_request(req: &HttpRequest, signer: &Signer) -> Option<Self> {
+        let cookie = req.cookie(SESSION_COOKIE)?;
+        decode_signed::<Self>(signer, SESSION_COOKIE, cookie.value())
+            .filter(|session| session.expires > OffsetDateTime::now_utc().unix_timestamp())
+    }
+}
+
+/// The identity of the logged in member, if login is enabled
+///
+/// # Errors
+///
+/// [Error::Unauthenticated] if login is required and the request has no valid session
+pub fn identity(
+    req: &HttpRequest,
+    config: &AppConfig,
+    runtime: &RuntimeData,
+) -> WResult<Option<Identity>> {
+    let Some(oidc) = &config.oidc else {
+        return Ok(None);
+    };
+
+    match Session::from_request(req, &runtime.signer) {
+        Some(session) => Ok(Some(session.identity)),
+        None if oidc.required => Err(Error::Unauthenticated),
+        None => Ok(None),
+    }
+}
+
+/// Encode a value as `<base64 JSON>.<signature>`.
+/// The purpose is signed along, so a value signed for one purpose is not valid for another.
+pub fn encode_signed<T: Serialize>(signer: &Signer, purpose: &str, value: &T) -> String {
+    let payload = BASE64_URL_SAFE_NO_PAD
+        .encode(serde_json::to_vec(value).expect("Session values serialize to JSON"));
+    let signature = signer.sign(&format!("{purpose}/{payload}"));
+    format!("{payload}.{signature}")
+}
+
+/// Decode a value encoded with [encode_signed], if its signature is valid
+pub fn decode_signed<T: DeserializeOwned>(
+    signer: &Signer,
+    purpose: &str,
+    value: &str,
+) -> Option<T> {
+    let (payload, signature) = value.rsplit_once('.')?;
+    if !signer.verify(&format!("{purpose}/{payload}"), signature) {
+        return None;
+    }
+
+    let json = BASE64_URL_SAFE_NO_PAD.decode(payload).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+/// An HTTP only cookie, only sent over https if the server is served over https.
+/// A `max_age` of zero removes the cookie.
+pub fn cookie(
+    config: &AppConfig,
+    name: &'static str,
+    value: String,
+    path: &'static str,
+    max_age: Duration,
+) -> Cookie<'static> {
+    Cookie::build(name, value)
+        .path(path)
+        .http_only(true)
+        .secure(config.server.public_url().starts_with("https://"))
+        // Lax, so the cookies are sent when the issuer redirects back
+        .same_site(SameSite::Lax)
+        .max_age(max_age)
+        .finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signed_values() {
+        let signer = Signer::new(b"secret");
+        let session = Session {
+            identity: Identity {
+                name: "Jan Jansen".to_string(),
+                email: "jan@example.com".to_string(),
+                address: None,
+                roles: vec![],
+            },
+            expires: 1_700_000_000,
+        };
+
+        let encoded = encode_signed(&signer, SESSION_COOKIE, &session);
+        let decoded = decode_signed::<Session>(&signer, SESSION_COOKIE, &encoded).unwrap();
+        assert_eq!(decoded.identity, session.identity);
+
+        // Signed for another purpose
+        assert!(decode_signed::<Session>(&signer, LOGIN_COOKIE, &encoded).is_none());
+
+        // Tampered with
+        let (_, signature) = encoded.rsplit_once('.').unwrap();
+        let forged = Session {
+            expires: i64::MAX,
+            ..session
+        };
+        let forged = format!(
+            "{}.{signature}",
+            BASE64_URL_SAFE_NO_PAD.encode(serde_json::to_vec(&forged).unwrap())
+        );
+        assert!(decode_signed::<Session>(&signer, SESSION_COOKIE, &forged).is_none());
+    }
+}
@@ -1,9 +1,18 @@
 use crate::args::AppArgs;
+use crate::email::address::SourceAddrMonitor;
+use crate::email::dkim::DkimSigner;
+use crate::email::template::Templates;
 use crate::file::AppConfig;
+use crate::i18n::{Catalog, Locale};
+use crate::oidc::OidcClient;
+use crate::server::bot::BotProtection;
+use crate::server::metrics::Metrics;
+use crate::server::rate_limit::RateLimiter;
+use crate::signing::Signer;
+use crate::storage::Storage;
 use actix_web::web;
-use serde::Deserialize;
-use std::net::Ipv4Addr;
 use std::sync::Arc;
+use std::time::Instant;
 use time::OffsetDateTime;
 
 pub type WConfig = web::Data<AppConfig>;
@@ -14,8 +23,21 @@ pub type WRuntime = web::Data<RuntimeData>;
 
 #[derive(Clone)]
 pub struct RuntimeData {
-    pub local_v4_addr: Ipv4Addr,
+    /// The local addresses SMTP connections are made from
+    pub source_addrs: Arc<SourceAddrMonitor>,
+    pub metrics: Arc<Metrics>,
+    pub rate_limiter: Arc<RateLimiter>,
+    pub bot_protection: Arc<BotProtection>,
+    pub dkim: Option<Arc<DkimSigner>>,
+    /// Set if members can log in
+    pub oidc: Option<Arc<OidcClient>>,
+    pub storage: Arc<Storage>,
+    pub signer: Signer,
+    pub templates: Arc<Templates>,
+    pub catalog: Arc<Catalog>,
     pub pending_digidecs: Arc<tokio::sync::Mutex<Vec<PendingDigidecs>>>,
+    /// The last result of the readiness check of the SMTP relay, with when it was made
+    pub smtp_check: Arc<tokio::sync::Mutex<Option<(Instant, bool)>>>,
 }
 
 #[derive(Clone)]
@@ -27,6 +49,40 @@ pub struct PendingDigidecs {
     pub attachments: Vec<PendingDigidecsAttachment>,
 }
 
+impl PendingDigidecs {
+    /// Approximate memory used by the declaration and its uploaded attachments, in bytes
+    pub fn size(&self) -> usize {
+        let data = &self.data;
+        let strings = [
+            &data.name,
+            &data.iban,
+            &data.email,
+            &data.address,
+            &data.what,
+            &data.commission,
+            &self.tracking_id,
+        ]
+        .iter()
+        .map(|s| s.len())
+        .sum::<usize>()
+            + data.notes.as_ref().map_or(0, String::len);
+
+        let attachments = self
+            .attachments
+            .iter()
+            .map(|att| {
+                att.name.len()
+                    + att.tracking_id.len()
+                    + att.mime.len()
+                    + att.content.as_ref().map_or(0, Vec::len)
+                    + att.partial.as_ref().map_or(0, |partial| partial.data.len())
+            })
+            .sum::<usize>();
+
+        std::mem::size_of::<Self>() + strings + attachments
+    }
+}
+
 #[derive(Clone)]
 pub struct PendingDigidecsData {
     pub name: String,
@@ -46,10 +102,13 @@ pub struct PendingDigidecsAttachment {
     pub tracking_id: String,
     pub mime: String,
     pub content: Option<Vec<u8>>,
+    /// An upload in chunks which has not received all bytes yet
+    pub partial: Option<PartialUpload>,
 }
 
-#[derive(Clone, Deserialize)]
-pub enum Locale {
-    En,
-    Nl,
+#[derive(Clone)]
+pub struct PartialUpload {
+    pub data: Vec<u8>,
+    /// The size of the complete attachment, in bytes
+    pub total: u64,
 }
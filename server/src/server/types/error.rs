@@ -1,7 +1,15 @@
+use crate::i18n::{Catalog, Locale};
+use crate::oidc::OidcError;
+use crate::storage::StorageError;
 use actix_web::body::BodyLimitExceeded;
+use actix_web::http::header::RETRY_AFTER;
 use actix_web::http::StatusCode;
-use actix_web::ResponseError;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::collections::BTreeMap;
 use thiserror::Error;
+use utoipa::openapi::{ContentBuilder, RefOr, Response, ResponseBuilder};
+use utoipa::{IntoResponses, ToSchema};
 
 pub type WResult<T> = Result<T, Error>;
 
@@ -9,6 +17,8 @@ pub type WResult<T> = Result<T, Error>;
 pub enum Error {
     #[error("Failed to send email: {0}")]
     Email(#[from] crate::email::SendError),
+    #[error("Failed to access storage: {0}")]
+    Storage(#[from] StorageError),
     #[error("Failed to render email body: {0}")]
     TemplateRender(#[from] handlebars::RenderError),
     #[error("Invalid IBAN")]
@@ -29,16 +39,79 @@ pub enum Error {
     UnknownAttachmentTrackingId,
     #[error("Digidecs has expired. Start over again")]
     DigidecsExpired,
-    #[error(transparent)]
-    BodyTooLarge(#[from] BodyLimitExceeded),
+    #[error("Invalid signature")]
+    InvalidSignature,
+    #[error("This link has expired")]
+    LinkExpired,
+    #[error("No attachment with that reference and index exists")]
+    UnknownAttachment,
+    #[error("Missing or invalid field {0}")]
+    InvalidField(&'static str),
+    #[error("Invalid form: {0}")]
+    InvalidForm(#[from] actix_multipart::MultipartError),
+    #[error("Invalid Content-Range header")]
+    InvalidContentRange,
+    #[error("Upload does not continue at the received offset of {0} bytes")]
+    UploadOffsetMismatch(u64),
+    #[error("Checksum of the uploaded attachment does not match")]
+    ChecksumMismatch,
+    #[error("Too many requests, retry after {} seconds", .0.as_secs())]
+    RateLimited(std::time::Duration),
+    #[error("Submission rejected as it appears to be made by a bot")]
+    BotDetected,
+    #[error("Missing, invalid or expired challenge")]
+    InvalidChallenge,
+    #[error("Submitted too fast, retry after {} seconds", .0.as_secs())]
+    SubmittedTooFast(std::time::Duration),
+    #[error("Log in to submit a declaration")]
+    Unauthenticated,
+    #[error("Logging in is not enabled")]
+    LoginUnavailable,
+    #[error("Missing or invalid login state. Log in again")]
+    InvalidLoginState,
+    #[error("Failed to log in: {0}")]
+    Login(#[from] OidcError),
+    #[error("Log in as treasurer or provide an API token")]
+    AdminUnauthenticated,
+    #[error("Not allowed to manage declarations")]
+    Forbidden,
+    #[error("No declaration with that reference exists")]
+    UnknownDeclaration,
+    #[error("Invalid fields: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    Validation(Vec<Error>),
+    #[error("Request body is too large")]
+    BodyTooLarge,
     #[error(transparent)]
     Actix(#[from] actix_web::Error),
 }
 
+impl From<BodyLimitExceeded> for Error {
+    fn from(_: BodyLimitExceeded) -> Self {
+        Self::BodyTooLarge
+    }
+}
+
+/// Report a stored declaration which does not exist as [Error::UnknownDeclaration]
+pub fn unknown_declaration(e: StorageError) -> Error {
+    match e {
+        StorageError::NotFound => Error::UnknownDeclaration,
+        e => Error::Storage(e),
+    }
+}
+
+/// Report a stored attachment, or its declaration, which does not exist as [Error::UnknownAttachment]
+pub fn unknown_attachment(e: StorageError) -> Error {
+    match e {
+        StorageError::NotFound => Error::UnknownAttachment,
+        e => Error::Storage(e),
+    }
+}
+
 impl ResponseError for Error {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::Email(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::TemplateRender(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::InvalidIban => StatusCode::BAD_REQUEST,
             Self::InvalidEmail => StatusCode::BAD_REQUEST,
@@ -49,8 +122,376 @@ impl ResponseError for Error {
             Self::UnknownTrackingId => StatusCode::NOT_FOUND,
             Self::UnknownAttachmentTrackingId => StatusCode::NOT_FOUND,
             Self::DigidecsExpired => StatusCode::BAD_REQUEST,
-            Self::BodyTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::InvalidSignature => StatusCode::FORBIDDEN,
+            Self::LinkExpired => StatusCode::GONE,
+            Self::UnknownAttachment => StatusCode::NOT_FOUND,
+            Self::InvalidField(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidForm(_) => StatusCode::BAD_REQUEST,
+            Self::InvalidContentRange => StatusCode::BAD_REQUEST,
+            Self::UploadOffsetMismatch(_) => StatusCode::CONFLICT,
+            Self::ChecksumMismatch => StatusCode::BAD_REQUEST,
+            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::BotDetected => StatusCode::BAD_REQUEST,
+            Self::InvalidChallenge => StatusCode::BAD_REQUEST,
+            Self::SubmittedTooFast(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Self::LoginUnavailable => StatusCode::NOT_FOUND,
+            Self::InvalidLoginState => StatusCode::BAD_REQUEST,
+            Self::Login(e) => match e {
+                OidcError::Denied(_) | OidcError::EmailNotVerified => StatusCode::FORBIDDEN,
+                // The login is not valid for this client, e.g. tampered with or replayed
+                OidcError::IdToken(_) => StatusCode::BAD_REQUEST,
+                OidcError::Http(_)
+                | OidcError::Url(_)
+                | OidcError::InsecureIssuer
+                | OidcError::IssuerMismatch(_)
+                | OidcError::Token(_) => StatusCode::BAD_GATEWAY,
+            },
+            Self::AdminUnauthenticated => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::UnknownDeclaration => StatusCode::NOT_FOUND,
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             Self::Actix(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        // The message is localized by the `localize_errors` middleware,
+        // as the locale of the request is not known here
+        let mut response = HttpResponse::build(self.status_code());
+        if let Self::RateLimited(retry_after) | Self::SubmittedTooFast(retry_after) = self {
+            // Round up, retrying before the limit resets would be pointless
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response.insert_header((RETRY_AFTER, secs.max(1)));
+        }
+        response.json(self.body())
+    }
+}
+
+/// The JSON body of an error response
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable, machine-readable identifier of the error
+    pub code: &'static str,
+    /// Human-readable message in the locale of the request
+    pub message: String,
+    /// The request field that caused the error, if any
+    pub field: Option<&'static str>,
+    /// The errors of the individual fields, if validation failed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[schema(no_recursion)]
+    pub errors: Vec<ErrorBody>,
+}
+
+impl Error {
+    /// An instance of every variant, used to document the possible errors
+    fn examples() -> Vec<Self> {
+        vec![
+            Self::Email(crate::email::SendError::Connect),
+            Self::Storage(crate::storage::StorageError::NotFound),
+            Self::TemplateRender(handlebars::RenderErrorReason::Other(String::new()).into()),
+            Self::InvalidIban,
+            Self::InvalidEmail,
+            Self::InvalidAddress,
+            Self::MissingAttachment,
+            Self::ValueNegativeOrZero,
+            Self::InvalidAttachmentBase64(base64::DecodeError::InvalidPadding),
+            Self::UnknownTrackingId,
+            Self::UnknownAttachmentTrackingId,
+            Self::DigidecsExpired,
+            Self::InvalidSignature,
+            Self::LinkExpired,
+            Self::UnknownAttachment,
+            Self::InvalidField("name"),
+            Self::InvalidForm(actix_multipart::MultipartError::Incomplete),
+            Self::InvalidContentRange,
+            Self::UploadOffsetMismatch(0),
+            Self::ChecksumMismatch,
+            Self::RateLimited(std::time::Duration::from_secs(60)),
+            Self::BotDetected,
+            Self::InvalidChallenge,
+            Self::SubmittedTooFast(std::time::Duration::from_secs(1)),
+            Self::Unauthenticated,
+            Self::LoginUnavailable,
+            Self::InvalidLoginState,
+            Self::Login(OidcError::Denied(String::new())),
+            Self::Login(OidcError::EmailNotVerified),
+            Self::Login(OidcError::Token(String::new())),
+            Self::AdminUnauthenticated,
+            Self::Forbidden,
+            Self::UnknownDeclaration,
+            Self::Validation(vec![]),
+            Self::BodyTooLarge,
+            Self::Actix(actix_web::error::ErrorInternalServerError("")),
+        ]
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Email(_) => "email_failed",
+            Self::Storage(_) => "storage_failed",
+            Self::TemplateRender(_) => "template_render_failed",
+            Self::InvalidIban => "invalid_iban",
+            Self::InvalidEmail => "invalid_email",
+            Self::InvalidAddress => "invalid_address",
+            Self::MissingAttachment => "missing_attachment",
+            Self::ValueNegativeOrZero => "value_not_positive",
+            Self::InvalidAttachmentBase64(_) => "invalid_attachment",
+            Self::UnknownTrackingId => "unknown_tracking_id",
+            Self::UnknownAttachmentTrackingId => "unknown_attachment_tracking_id",
+            Self::DigidecsExpired => "digidecs_expired",
+            Self::InvalidSignature => "invalid_signature",
+            Self::LinkExpired => "link_expired",
+            Self::UnknownAttachment => "unknown_attachment",
+            Self::InvalidField(_) => "invalid_field",
+            Self::InvalidForm(_) => "invalid_form",
+            Self::InvalidContentRange => "invalid_content_range",
+            Self::UploadOffsetMismatch(_) => "upload_offset_mismatch",
+            Self::ChecksumMismatch => "checksum_mismatch",
+            Self::RateLimited(_) => "rate_limited",
+            Self::BotDetected => "bot_detected",
+            Self::InvalidChallenge => "invalid_challenge",
+            Self::SubmittedTooFast(_) => "submitted_too_fast",
+            Self::Unauthenticated => "unauthenticated",
+            Self::LoginUnavailable => "login_unavailable",
+            Self::InvalidLoginState => "invalid_login_state",
+            Self::Login(OidcError::Denied(_)) => "login_denied",
+            Self::Login(OidcError::EmailNotVerified) => "email_not_verified",
+            Self::Login(_) => "login_failed",
+            Self::AdminUnauthenticated => "admin_unauthenticated",
+            Self::Forbidden => "forbidden",
+            Self::UnknownDeclaration => "unknown_declaration",
+            Self::Validation(_) => "validation_failed",
+            Self::BodyTooLarge => "body_too_large",
+            Self::Actix(_) => "internal",
+        }
+    }
+
+    pub fn field(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidIban => Some("iban"),
+            Self::InvalidEmail => Some("email"),
+            Self::InvalidAddress => Some("address"),
+            Self::MissingAttachment => Some("attachments"),
+            Self::ValueNegativeOrZero => Some("value"),
+            Self::InvalidAttachmentBase64(_) => Some("attachment"),
+            Self::UnknownTrackingId => Some("tracking_id"),
+            Self::UnknownAttachmentTrackingId => Some("attachment_tracking_id"),
+            Self::InvalidSignature => Some("signature"),
+            Self::LinkExpired => Some("expires"),
+            Self::UnknownAttachment => Some("attachment"),
+            Self::InvalidField(field) => Some(field),
+            Self::ChecksumMismatch => Some("sha256"),
+            Self::BotDetected => Some("website"),
+            Self::InvalidChallenge => Some("challenge"),
+            Self::UnknownDeclaration => Some("reference"),
+            _ => None,
+        }
+    }
+
+    /// The ID of the message in the catalog.
+    /// Internal errors share a generic message, their details are of no use to users.
+    fn message_id(&self) -> &'static str {
+        match self {
+            Self::Email(_) | Self::Storage(_) | Self::TemplateRender(_) | Self::Actix(_) => {
+                "error-internal"
+            }
+            Self::InvalidIban => "error-invalid-iban",
+            Self::InvalidEmail => "error-invalid-email",
+            Self::InvalidAddress => "error-invalid-address",
+            Self::MissingAttachment => "error-missing-attachment",
+            Self::ValueNegativeOrZero => "error-value-not-positive",
+            Self::InvalidAttachmentBase64(_) => "error-invalid-attachment",
+            Self::UnknownTrackingId => "error-unknown-tracking-id",
+            Self::UnknownAttachmentTrackingId => "error-unknown-attachment-tracking-id",
+            Self::DigidecsExpired => "error-digidecs-expired",
+            Self::InvalidSignature => "error-invalid-signature",
+            Self::LinkExpired => "error-link-expired",
+            Self::UnknownAttachment => "error-unknown-attachment",
+            Self::InvalidField(_) => "error-invalid-field",
+            Self::InvalidForm(_) => "error-invalid-form",
+            Self::InvalidContentRange => "error-invalid-content-range",
+            Self::UploadOffsetMismatch(_) => "error-upload-offset-mismatch",
+            Self::ChecksumMismatch => "error-checksum-mismatch",
+            Self::RateLimited(_) => "error-rate-limited",
+            Self::BotDetected => "error-bot-detected",
+            Self::InvalidChallenge => "error-invalid-challenge",
+            Self::SubmittedTooFast(_) => "error-submitted-too-fast",
+            Self::Unauthenticated => "error-unauthenticated",
+            Self::LoginUnavailable => "error-login-unavailable",
+            Self::InvalidLoginState => "error-invalid-login-state",
+            Self::Login(OidcError::Denied(_)) => "error-login-denied",
+            Self::Login(OidcError::EmailNotVerified) => "error-email-not-verified",
+            Self::Login(_) => "error-login-failed",
+            Self::AdminUnauthenticated => "error-admin-unauthenticated",
+            Self::Forbidden => "error-forbidden",
+            Self::UnknownDeclaration => "error-unknown-declaration",
+            Self::Validation(_) => "error-validation",
+            Self::BodyTooLarge => "error-body-too-large",
+        }
+    }
+
+    fn field_errors(&self) -> &[Error] {
+        match self {
+            Self::Validation(errors) => errors,
+            _ => &[],
+        }
+    }
+
+    fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            field: self.field(),
+            errors: self.field_errors().iter().map(Self::body).collect(),
+        }
+    }
+
+    /// The error response body, with the message in the provided locale
+    pub fn localized_body(&self, catalog: &Catalog, locale: &Locale) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            message: catalog
+                .format(locale, self.message_id(), None)
+                .unwrap_or_else(|| self.to_string()),
+            field: self.field(),
+            errors: self
+                .field_errors()
+                .iter()
+                .map(|error| error.localized_body(catalog, locale))
+                .collect(),
+        }
+    }
+}
+
+/// Every error status code, with the error codes it is returned with
+impl IntoResponses for Error {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let mut codes = BTreeMap::<u16, Vec<&'static str>>::new();
+        for error in Self::examples() {
+            let codes = codes.entry(error.status_code().as_u16()).or_default();
+            if !codes.contains(&error.code()) {
+                codes.push(error.code());
+            }
+        }
+
+        codes
+            .into_iter()
+            .map(|(status, codes)| {
+                let response = ResponseBuilder::new()
+                    .description(format!("Error with code {}", codes.join(", ")))
+                    .content(
+                        "application/json",
+                        ContentBuilder::new()
+                            .schema(Some(RefOr::Ref(utoipa::openapi::Ref::from_schema_name(
+                                ErrorBody::name(),
+                            ))))
+                            .build(),
+                    )
+                    .build();
+                (status.to_string(), RefOr::T(response))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn localized_messages() {
+        let catalog = Catalog::load(None, Locale::default()).unwrap();
+        let errors = Error::examples();
+
+        for locale in catalog.locales() {
+            for error in &errors {
+                assert!(
+                    catalog.format(locale, error.message_id(), None).is_some(),
+                    "No message for {} in {locale}",
+                    error.code()
+                );
+            }
+        }
+
+        let body = Error::InvalidIban.localized_body(&catalog, &Locale::new("en"));
+        assert_eq!(body.code, "invalid_iban");
+        assert_eq!(body.field, Some("iban"));
+        assert_ne!(
+            body.message,
+            Error::InvalidIban
+                .localized_body(&catalog, &Locale::new("nl"))
+                .message
+        );
+    }
+
+    #[test]
+    fn login_status() {
+        assert_eq!(
+            Error::Login(OidcError::Denied(String::new())).status_code(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            Error::Login(OidcError::Token(String::new())).status_code(),
+            StatusCode::BAD_GATEWAY
+        );
+    }
+
+    /// The index of the variant of an error. The match has no wildcard, so adding a variant
+    /// fails to compile until it is added here, after which [examples_cover_every_variant]
+    /// fails until it is added to [Error::examples].
+    fn variant_index(error: &Error) -> usize {
+        match error {
+            Error::Email(_) => 0,
+            Error::Storage(_) => 1,
+            Error::TemplateRender(_) => 2,
+            Error::InvalidIban => 3,
+            Error::InvalidEmail => 4,
+            Error::InvalidAddress => 5,
+            Error::MissingAttachment => 6,
+            Error::ValueNegativeOrZero => 7,
+            Error::InvalidAttachmentBase64(_) => 8,
+            Error::UnknownTrackingId => 9,
+            Error::UnknownAttachmentTrackingId => 10,
+            Error::DigidecsExpired => 11,
+            Error::InvalidSignature => 12,
+            Error::LinkExpired => 13,
+            Error::UnknownAttachment => 14,
+            Error::InvalidField(_) => 15,
+            Error::InvalidForm(_) => 16,
+            Error::InvalidContentRange => 17,
+            Error::UploadOffsetMismatch(_) => 18,
+            Error::ChecksumMismatch => 19,
+            Error::RateLimited(_) => 20,
+            Error::BotDetected => 21,
+            Error::InvalidChallenge => 22,
+            Error::SubmittedTooFast(_) => 23,
+            Error::Unauthenticated => 24,
+            Error::LoginUnavailable => 25,
+            Error::InvalidLoginState => 26,
+            Error::Login(_) => 27,
+            Error::AdminUnauthenticated => 28,
+            Error::Forbidden => 29,
+            Error::UnknownDeclaration => 30,
+            Error::Validation(_) => 31,
+            Error::BodyTooLarge => 32,
+            Error::Actix(_) => 33,
+        }
+    }
+
+    #[test]
+    fn examples_cover_every_variant() {
+        let covered = Error::examples()
+            .iter()
+            .map(variant_index)
+            .collect::<std::collections::BTreeSet<_>>();
+
+        for index in 0..34 {
+            assert!(
+                covered.contains(&index),
+                "Variant {index} of variant_index is missing from the examples"
+            );
+        }
+    }
 }
@@ -1,7 +1,5 @@
 pub mod data;
-pub mod empty;
 pub mod error;
 
 pub use data::*;
-pub use empty::*;
 pub use error::*;
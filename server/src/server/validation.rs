@@ -0,0 +1,105 @@
+use crate::server::types::{Error, WResult};
+
+/// A request which can be validated before it is handled
+pub trait Validate {
+    /// Check every field of the request.
+    ///
+    /// # Errors
+    ///
+    /// [Error::Validation] with the errors of all invalid fields
+    fn validate(&self) -> WResult<()>;
+}
+
+/// Collects the errors of all invalid fields,
+/// so that they can be reported in a single response.
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<Error>,
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record `error` if `valid` is false.
+    /// Only the first error of every field is recorded.
+    pub fn check(&mut self, valid: bool, error: Error) -> &mut Self {
+        let field_reported =
+            error.field().is_some() && self.errors.iter().any(|e| e.field() == error.field());
+
+        if !valid && !field_reported {
+            self.errors.push(error);
+        }
+        self
+    }
+
+    /// Record the errors of another validation
+    pub fn merge(&mut self, result: WResult<()>) -> &mut Self {
+        match result {
+            Ok(()) => {}
+            Err(Error::Validation(errors)) => {
+                for error in errors {
+                    self.check(false, error);
+                }
+            }
+            Err(error) => {
+                self.check(false, error);
+            }
+        }
+        self
+    }
+
+    /// # Errors
+    ///
+    /// [Error::Validation] if any check failed
+    pub fn finish(&mut self) -> WResult<()> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(std::mem::take(&mut self.errors)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn collects_all_errors() {
+        let result = Validator::new()
+            .check(false, Error::InvalidEmail)
+            .check(true, Error::InvalidIban)
+            .check(false, Error::ValueNegativeOrZero)
+            .finish();
+
+        let Err(Error::Validation(errors)) = result else {
+            panic!("Expected validation errors");
+        };
+        assert_eq!(
+            errors.iter().map(Error::code).collect::<Vec<_>>(),
+            vec!["invalid_email", "value_not_positive"]
+        );
+
+        let result = Validator::new()
+            .check(false, Error::InvalidField("email"))
+            .merge(Err(Error::Validation(vec![
+                Error::InvalidEmail,
+                Error::InvalidIban,
+            ])))
+            .finish();
+        let Err(Error::Validation(errors)) = result else {
+            panic!("Expected validation errors");
+        };
+        assert_eq!(
+            errors.iter().map(Error::code).collect::<Vec<_>>(),
+            vec!["invalid_field", "invalid_iban"]
+        );
+
+        assert!(Validator::new()
+            .check(true, Error::InvalidEmail)
+            .finish()
+            .is_ok());
+    }
+}
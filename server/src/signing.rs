@@ -0,0 +1,65 @@
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs values handed out to clients, so that they can be verified when they come back
+#[derive(Clone)]
+pub struct Signer {
+    key: Vec<u8>,
+}
+
+impl Signer {
+    pub fn new(key: &[u8]) -> Self {
+        Self { key: key.to_vec() }
+    }
+
+    /// Create a signer with a random key.
+    /// Signatures created by it are invalid once the server restarts.
+    pub fn random() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self::new(&key)
+    }
+
+    fn mac(&self) -> HmacSha256 {
+        HmacSha256::new_from_slice(&self.key).expect("HMAC accepts keys of any length")
+    }
+
+    /// Sign the message, returning the URL-safe base64 encoded signature
+    pub fn sign(&self, message: &str) -> String {
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        BASE64_URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    /// Verify a signature created by [Self::sign] in constant time
+    pub fn verify(&self, message: &str, signature: &str) -> bool {
+        let Ok(signature) = BASE64_URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+
+        let mut mac = self.mac();
+        mac.update(message.as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sign_and_verify() {
+        let signer = Signer::new(b"secret");
+        let signature = signer.sign("DD-2026-0001/0/1700000000");
+
+        assert!(signer.verify("DD-2026-0001/0/1700000000", &signature));
+        assert!(!signer.verify("DD-2026-0001/1/1700000000", &signature));
+        assert!(!signer.verify("DD-2026-0001/0/1700000000", "invalid"));
+        assert!(!Signer::new(b"other").verify("DD-2026-0001/0/1700000000", &signature));
+    }
+}
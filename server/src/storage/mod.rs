@@ -0,0 +1,487 @@
+use std::path::{Path, PathBuf};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use tracing::{debug, trace, warn};
+use utoipa::ToSchema;
+
+pub use reference::*;
+pub use tokens::*;
+
+use crate::email::routing::Recipients;
+use crate::file::{DataFile, DataFileError};
+
+mod reference;
+mod tokens;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("{0}")]
+    DataFile(#[from] DataFileError),
+    #[error("The requested item does not exist")]
+    NotFound,
+    #[error("The item already exists")]
+    AlreadyExists,
+}
+
+/// A completed declaration, as it was sent to the treasurer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Declaration {
+    pub reference: Reference,
+    #[serde(with = "time::serde::rfc3339")]
+    pub submitted_at: OffsetDateTime,
+    pub name: String,
+    pub iban: String,
+    pub email: String,
+    pub address: String,
+    pub value: f64,
+    pub what: String,
+    pub commission: String,
+    pub notes: Option<String>,
+    pub attachments: Vec<StoredAttachment>,
+    /// Who the treasurer email was sent to, and the routing rule that determined it
+    pub recipients: Recipients,
+    #[serde(default)]
+    pub status: DeclarationStatus,
+    /// Changes of the status by the treasurer, oldest first
+    #[serde(default)]
+    pub history: Vec<StatusChange>,
+}
+
+/// Where a declaration is in its handling by the treasurer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeclarationStatus {
+    #[default]
+    Submitted,
+    Approved,
+    Rejected,
+    Paid,
+    /// The email to the treasurer could not be sent
+    Undelivered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StatusChange {
+    pub status: DeclarationStatus,
+    #[serde(with = "time::serde::rfc3339")]
+    #[schema(value_type = String, format = DateTime)]
+    pub at: OffsetDateTime,
+    /// The treasurer or API token that changed the status
+    pub by: String,
+    pub note: Option<String>,
+}
+
+/// An attachment of a declaration.
+/// The content is stored separately, see [Storage::save_attachment]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredAttachment {
+    pub name: String,
+    pub mime: String,
+}
+
+/// Persistent storage of declarations on disk
+pub struct Storage {
+    root: PathBuf,
+    /// Serializes access to the reference counter
+    counter_lock: Mutex<()>,
+    /// Serializes updates of stored declarations
+    update_lock: Mutex<()>,
+}
+
+const COUNTER_FILE: &str = "references.json";
+const TOKENS_FILE: &str = "api_tokens.json";
+const DECLARATIONS_DIR: &str = "declarations";
+const SPOOL_DIR: &str = "spool";
+
+impl Storage {
+    /// Open the storage in the provided directory, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// If the directories could not be created
+    pub async fn open<P: AsRef<Path>>(root: P) -> Result<Self, StorageError> {
+        let root = root.as_ref().to_path_buf();
+        debug!("Using {} for storage", root.display());
+
+        fs::create_dir_all(root.join(DECLARATIONS_DIR)).await?;
+        fs::create_dir_all(root.join(SPOOL_DIR)).await?;
+
+        Ok(Self {
+            root,
+            counter_lock: Mutex::new(()),
+            update_lock: Mutex::new(()),
+        })
+    }
+
+    /// Allocate the next reference number.
+    /// The counter is persisted before the reference is returned,
+    /// so a reference is never handed out twice.
+    ///
+    /// # Errors
+    ///
+    /// If the counter could not be read or written
+    pub async fn next_reference(&self) -> Result<Reference, StorageError> {
+        let _guard = self.counter_lock.lock().await;
+        let path = self.root.join(COUNTER_FILE);
+
+        let mut counter = ReferenceCounter::try_read(&path, false).await?;
+        let reference = counter.next(OffsetDateTime::now_utc().year());
+        // A partially written counter would make every later read fail
+        write_atomic(&path, &serde_json::to_vec_pretty(&counter)?).await?;
+
+        trace!("Allocated reference {reference}");
+        Ok(reference)
+    }
+
+    /// Persist a new declaration. An existing declaration is never overwritten,
+    /// e.g. if the reference counter was reset.
+    ///
+    /// # Errors
+    ///
+    /// [StorageError::AlreadyExists] if a declaration with the same reference exists,
+    /// or if the declaration could not be written
+    pub async fn save_declaration(&self, declaration: &Declaration) -> Result<(), StorageError> {
+        write_atomic_new(
+            &self.declaration_path(&declaration.reference),
+            &serde_json::to_vec_pretty(declaration)?,
+        )
+        .await
+    }
+
+    /// Load a previously saved declaration.
+    ///
+    /// # Errors
+    ///
+    /// If the declaration does not exist or could not be read
+    pub async fn load_declaration(
+        &self,
+        reference: &Reference,
+    ) -> Result<Declaration, StorageError> {
+        let contents = fs::read(self.declaration_path(reference))
+            .await
+            .map_err(not_found)?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    /// Load all saved declarations, newest first.
+    /// Declarations that cannot be read are skipped and logged.
+    ///
+    /// # Errors
+    ///
+    /// If the declarations directory could not be read
+    pub async fn list_declarations(&self) -> Result<Vec<Declaration>, StorageError> {
+        let mut declarations = vec![];
+        let mut entries = fs::read_dir(self.root.join(DECLARATIONS_DIR)).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            // One damaged file should not make every other declaration unavailable
+            let declaration = match fs::read(&path).await {
+                Ok(contents) => {
+                    serde_json::from_slice::<Declaration>(&contents).map_err(StorageError::from)
+                }
+                Err(e) => Err(e.into()),
+            };
+            match declaration {
+                Ok(declaration) => declarations.push(declaration),
+                Err(e) => warn!("Skipping unreadable declaration {}: {e}", path.display()),
+            }
+        }
+
+        declarations.sort_by_key(|declaration| std::cmp::Reverse(declaration.reference));
+        Ok(declarations)
+    }
+
+    /// Apply `update` to a saved declaration and persist the result.
+    ///
+    /// # Errors
+    ///
+    /// If the declaration does not exist or could not be read or written
+    pub async fn update_declaration<F: FnOnce(&mut Declaration)>(
+        &self,
+        reference: &Reference,
+        update: F,
+    ) -> Result<Declaration, StorageError> {
+        let _guard = self.update_lock.lock().await;
+
+        let mut declaration = self.load_declaration(reference).await?;
+        update(&mut declaration);
+        write_atomic(
+            &self.declaration_path(reference),
+            &serde_json::to_vec_pretty(&declaration)?,
+        )
+        .await?;
+
+        Ok(declaration)
+    }
+
+    /// Load the API tokens. Read on every use, so changes made with the CLI apply immediately.
+    ///
+    /// # Errors
+    ///
+    /// If the tokens could not be read
+    pub async fn load_api_tokens(&self) -> Result<ApiTokens, StorageError> {
+        Ok(ApiTokens::try_read(self.root.join(TOKENS_FILE), false).await?)
+    }
+
+    /// Persist the API tokens.
+    ///
+    /// # Errors
+    ///
+    /// If the tokens could not be written
+    pub async fn save_api_tokens(&self, tokens: &ApiTokens) -> Result<(), StorageError> {
+        Ok(tokens.try_write(self.root.join(TOKENS_FILE)).await?)
+    }
+
+    /// Persist the content of the attachment at `index` in [Declaration::attachments].
+    ///
+    /// # Errors
+    ///
+    /// [StorageError::AlreadyExists] if the attachment exists,
+    /// or if the attachment could not be written
+    pub async fn save_attachment(
+        &self,
+        reference: &Reference,
+        index: usize,
+        content: &[u8],
+    ) -> Result<(), StorageError> {
+        let dir = self.attachments_dir(reference);
+        fs::create_dir_all(&dir).await?;
+        let mut file = fs::File::create_new(dir.join(index.to_string()))
+            .await
+            .map_err(already_exists)?;
+        file.write_all(content).await?;
+        Ok(())
+    }
+
+    /// Read the content of the attachment at `index` in [Declaration::attachments].
+    ///
+    /// # Errors
+    ///
+    /// If the attachment does not exist or could not be read
+    pub async fn read_attachment(
+        &self,
+        reference: &Reference,
+        index: usize,
+    ) -> Result<Vec<u8>, StorageError> {
+        fs::read(self.attachments_dir(reference).join(index.to_string()))
+            .await
+            .map_err(not_found)
+    }
+
+    /// Directory in which uploads are kept until the declaration is completed
+    pub fn spool_dir(&self) -> PathBuf {
+        self.root.join(SPOOL_DIR)
+    }
+
+    /// Create a new, uniquely named, file in the spool directory
+    ///
+    /// # Errors
+    ///
+    /// If the file could not be created
+    pub async fn create_spool_file(&self) -> Result<(PathBuf, fs::File), StorageError> {
+        let path = self.spool_dir().join(random_name());
+        let file = fs::File::create_new(&path).await?;
+        Ok((path, file))
+    }
+
+    /// Check that stored declarations can be read.
+    ///
+    /// # Errors
+    ///
+    /// If the declarations directory could not be read
+    pub async fn check_declarations(&self) -> Result<(), StorageError> {
+        let _ = fs::read_dir(self.root.join(DECLARATIONS_DIR)).await?;
+        Ok(())
+    }
+
+    /// Check that uploads can be written to the spool directory.
+    ///
+    /// # Errors
+    ///
+    /// If a file could not be written to or removed from the spool directory
+    pub async fn check_spool_writable(&self) -> Result<(), StorageError> {
+        // A unique file, concurrent checks must not remove each other's file
+        let (path, mut file) = self.create_spool_file().await?;
+        let written = file.write_all(b"ok").await;
+        drop(file);
+        fs::remove_file(&path).await?;
+        Ok(written?)
+    }
+
+    fn declaration_path(&self, reference: &Reference) -> PathBuf {
+        self.root
+            .join(DECLARATIONS_DIR)
+            .join(format!("{reference}.json"))
+    }
+
+    fn attachments_dir(&self, reference: &Reference) -> PathBuf {
+        self.root.join(DECLARATIONS_DIR).join(reference.to_string())
+    }
+}
+
+/// Write to a temporary file next to `path` and rename it over `path`,
+/// so readers see either the old or the new contents, never a partially written file
+async fn write_atomic(path: &Path, contents: &[u8]) -> Result<(), StorageError> {
+    let tmp = write_tmp(path, contents).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// Like [write_atomic], but fails with [StorageError::AlreadyExists] if `path` exists
+async fn write_atomic_new(path: &Path, contents: &[u8]) -> Result<(), StorageError> {
+    let tmp = write_tmp(path, contents).await?;
+    // Unlike a rename, a hard link does not replace an existing file
+    let linked = fs::hard_link(&tmp, path).await;
+    fs::remove_file(&tmp).await?;
+    linked.map_err(already_exists)
+}
+
+/// Write `contents` to a new temporary file next to `path`
+async fn write_tmp(path: &Path, contents: &[u8]) -> Result<PathBuf, StorageError> {
+    // Unique, so concurrent writes of the same file do not share a temporary file
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}.tmp", random_name()));
+    let tmp = PathBuf::from(tmp);
+
+    let mut file = fs::File::create(&tmp).await?;
+    file.write_all(contents).await?;
+    file.sync_all().await?;
+    Ok(tmp)
+}
+
+fn random_name() -> String {
+    rand::thread_rng()
+        .sample_iter(rand::distributions::Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect()
+}
+
+fn not_found(e: std::io::Error) -> StorageError {
+    match e.kind() {
+        std::io::ErrorKind::NotFound => StorageError::NotFound,
+        _ => StorageError::Io(e),
+    }
+}
+
+fn already_exists(e: std::io::Error) -> StorageError {
+    match e.kind() {
+        std::io::ErrorKind::AlreadyExists => StorageError::AlreadyExists,
+        _ => StorageError::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::email::routing::Recipients;
+
+    fn declaration(number: u32) -> Declaration {
+        Declaration {
+            reference: Reference { year: 2026, number },
+            submitted_at: OffsetDateTime::now_utc(),
+            name: "Jan Jansen".to_string(),
+            iban: "NL91ABNA0417164300".to_string(),
+            email: "jan@example.com".to_string(),
+            address: "Straat 1".to_string(),
+            value: 12.5,
+            what: "Pizza".to_string(),
+            commission: "Bestuur".to_string(),
+            notes: None,
+            attachments: vec![],
+            recipients: Recipients {
+                rule: None,
+                to: vec![],
+                cc: vec![],
+                bcc: vec![],
+            },
+            status: DeclarationStatus::Submitted,
+            history: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn list_skips_unreadable_declarations() {
+        let root = std::env::temp_dir().join(format!("digidecs-test-{}", random_name()));
+        let storage = Storage::open(&root).await.unwrap();
+
+        storage.save_declaration(&declaration(1)).await.unwrap();
+        storage.save_declaration(&declaration(2)).await.unwrap();
+        fs::write(
+            root.join(DECLARATIONS_DIR).join("DD-2026-0003.json"),
+            b"{\"refer",
+        )
+        .await
+        .unwrap();
+
+        let references = storage
+            .list_declarations()
+            .await
+            .unwrap()
+            .iter()
+            .map(|declaration| declaration.reference.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(references, vec!["DD-2026-0002", "DD-2026-0001"]);
+
+        // No temporary files are left behind
+        let mut entries = fs::read_dir(root.join(DECLARATIONS_DIR)).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            assert!(!entry.file_name().to_string_lossy().ends_with(".tmp"));
+        }
+
+        fs::remove_dir_all(root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn never_overwrites_declarations() {
+        let root = std::env::temp_dir().join(format!("digidecs-test-{}", random_name()));
+        let storage = Storage::open(&root).await.unwrap();
+
+        storage.save_declaration(&declaration(1)).await.unwrap();
+        storage
+            .save_attachment(
+                &Reference {
+                    year: 2026,
+                    number: 1,
+                },
+                0,
+                b"receipt",
+            )
+            .await
+            .unwrap();
+
+        let mut other = declaration(1);
+        other.name = "Piet Pietersen".to_string();
+        assert!(matches!(
+            storage.save_declaration(&other).await,
+            Err(StorageError::AlreadyExists)
+        ));
+        assert!(matches!(
+            storage.save_attachment(&other.reference, 0, b"other").await,
+            Err(StorageError::AlreadyExists)
+        ));
+
+        let stored = storage.load_declaration(&other.reference).await.unwrap();
+        assert_eq!(stored.name, "Jan Jansen");
+        assert_eq!(
+            storage.read_attachment(&other.reference, 0).await.unwrap(),
+            b"receipt"
+        );
+
+        fs::remove_dir_all(root).await.unwrap();
+    }
+}
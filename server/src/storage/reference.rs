@@ -0,0 +1,116 @@
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::file::DataFile;
+
+/// Human readable reference of a declaration, e.g. `DD-2026-0042`.
+/// Numbers are sequential and restart every year.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Reference {
+    pub year: i32,
+    pub number: u32,
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid reference")]
+pub struct InvalidReference;
+
+impl Display for Reference {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "DD-{}-{:04}", self.year, self.number)
+    }
+}
+
+impl FromStr for Reference {
+    type Err = InvalidReference;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('-');
+        if parts.next() != Some("DD") {
+            return Err(InvalidReference);
+        }
+
+        let year = parts
+            .next()
+            .and_then(|year| year.parse().ok())
+            .ok_or(InvalidReference)?;
+        let number = parts
+            .next()
+            .and_then(|number| number.parse().ok())
+            .ok_or(InvalidReference)?;
+
+        if parts.next().is_some() {
+            return Err(InvalidReference);
+        }
+
+        Ok(Self { year, number })
+    }
+}
+
+impl Serialize for Reference {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Reference {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// The last handed out reference
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ReferenceCounter {
+    year: i32,
+    last: u32,
+}
+
+impl ReferenceCounter {
+    /// Advance the counter, restarting at 1 if a new year has started
+    pub fn next(&mut self, year: i32) -> Reference {
+        if self.year != year {
+            self.year = year;
+            self.last = 0;
+        }
+
+        self.last += 1;
+        Reference {
+            year: self.year,
+            number: self.last,
+        }
+    }
+}
+
+impl DataFile for ReferenceCounter {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_and_parse() {
+        let reference = Reference {
+            year: 2026,
+            number: 42,
+        };
+        assert_eq!(reference.to_string(), "DD-2026-0042");
+        assert_eq!("DD-2026-0042".parse::<Reference>().unwrap(), reference);
+        assert!("DD-2026".parse::<Reference>().is_err());
+        assert!("XX-2026-0042".parse::<Reference>().is_err());
+    }
+
+    #[test]
+    fn counter_restarts_every_year() {
+        let mut counter = ReferenceCounter::default();
+        assert_eq!(counter.next(2026).to_string(), "DD-2026-0001");
+        assert_eq!(counter.next(2026).to_string(), "DD-2026-0002");
+        assert_eq!(counter.next(2027).to_string(), "DD-2027-0001");
+    }
+}
@@ -0,0 +1,95 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use crate::file::DataFile;
+
+/// Prefix of API tokens, so they are recognizable when leaked
+const TOKEN_PREFIX: &str = "dd_";
+
+/// The API tokens giving access to the admin API.
+/// Only the SHA-256 of a token is stored, the token itself is shown once when it is created.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ApiTokens {
+    pub tokens: Vec<ApiToken>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Unique name, describing who or what uses the token
+    pub name: String,
+    /// Hex encoded SHA-256 of the token
+    pub hash: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+impl ApiTokens {
+    /// Create a token with the provided name, replacing any existing token with that name.
+    /// Returns the token, which cannot be recovered afterwards.
+    pub fn create(&mut self, name: &str) -> String {
+        let token = format!(
+            "{TOKEN_PREFIX}{}",
+            rand::thread_rng()
+                .sample_iter(rand::distributions::Alphanumeric)
+                .take(40)
+                .map(char::from)
+                .collect::<String>()
+        );
+
+        self.revoke(name);
+        self.tokens.push(ApiToken {
+            name: name.to_string(),
+            hash: hash(&token),
+            created_at: OffsetDateTime::now_utc(),
+        });
+
+        token
+    }
+
+    /// Remove the token with the provided name. Returns whether it existed.
+    pub fn revoke(&mut self, name: &str) -> bool {
+        let count = self.tokens.len();
+        self.tokens.retain(|token| token.name != name);
+        self.tokens.len() != count
+    }
+
+    /// The stored token matching `token`, if any
+    pub fn find(&self, token: &str) -> Option<&ApiToken> {
+        // Tokens are random, so comparing their hashes reveals nothing useful through timing
+        let hash = hash(token);
+        self.tokens.iter().find(|stored| stored.hash == hash)
+    }
+}
+
+impl DataFile for ApiTokens {}
+
+fn hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn create_find_revoke() {
+        let mut tokens = ApiTokens::default();
+        let token = tokens.create("bookkeeping");
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_ne!(tokens.tokens[0].hash, token);
+        assert_eq!(tokens.find(&token).unwrap().name, "bookkeeping");
+        assert!(tokens.find("dd_wrong").is_none());
+
+        // Recreating replaces the old token
+        let new = tokens.create("bookkeeping");
+        assert_eq!(tokens.tokens.len(), 1);
+        assert!(tokens.find(&token).is_none());
+        assert!(tokens.find(&new).is_some());
+
+        assert!(tokens.revoke("bookkeeping"));
+        assert!(!tokens.revoke("bookkeeping"));
+        assert!(tokens.find(&new).is_none());
+    }
+}
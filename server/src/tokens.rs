@@ -0,0 +1,36 @@
+use color_eyre::eyre::eyre;
+
+use crate::args::TokenCommand;
+use crate::file::AppConfig;
+use crate::storage::Storage;
+
+/// Create, list or revoke API tokens for the admin API
+pub async fn manage(config: &AppConfig, command: &TokenCommand) -> color_eyre::Result<()> {
+    let storage = Storage::open(&config.storage.data_dir).await?;
+    let mut tokens = storage.load_api_tokens().await?;
+
+    match command {
+        TokenCommand::Create { name } => {
+            let token = tokens.create(name);
+            storage.save_api_tokens(&tokens).await?;
+
+            println!("Created token {name}. Store it now, it is not shown again:");
+            println!("{token}");
+        }
+        TokenCommand::List => {
+            for token in &tokens.tokens {
+                println!("{}\tcreated {}", token.name, token.created_at.date());
+            }
+        }
+        TokenCommand::Revoke { name } => {
+            if !tokens.revoke(name) {
+                return Err(eyre!("No token named {name} exists"));
+            }
+            storage.save_api_tokens(&tokens).await?;
+
+            println!("Revoked token {name}");
+        }
+    }
+
+    Ok(())
+}